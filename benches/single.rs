@@ -0,0 +1,47 @@
+#[macro_use] extern crate criterion;
+#[macro_use] extern crate runtime_fmt;
+
+use criterion::{black_box, Criterion};
+use runtime_fmt::{FormatBuf, Param, PreparedFormat};
+
+// "{}" and "{:?}" skip the parser; "{0}" is the same format by way of it.
+
+fn single_display(c: &mut Criterion) {
+    c.bench_function("single_display", |b| {
+        b.iter(|| rt_format!(black_box("{}"), black_box(42)).unwrap())
+    });
+    c.bench_function("single_display_parsed", |b| {
+        b.iter(|| rt_format!(black_box("{0}"), black_box(42)).unwrap())
+    });
+}
+
+fn single_debug(c: &mut Criterion) {
+    c.bench_function("single_debug", |b| {
+        b.iter(|| rt_format!(black_box("{:?}"), black_box("text")).unwrap())
+    });
+    c.bench_function("single_debug_parsed", |b| {
+        b.iter(|| rt_format!(black_box("{0:?}"), black_box("text")).unwrap())
+    });
+}
+
+fn single_buffer(c: &mut Criterion) {
+    let params = [Param::normal(&42)];
+    c.bench_function("single_buffer", |b| {
+        b.iter(|| FormatBuf::new(black_box("{}"), &params).map(|buf| buf.arg_count()))
+    });
+    c.bench_function("single_buffer_parsed", |b| {
+        b.iter(|| FormatBuf::new(black_box("{0}"), &params).map(|buf| buf.arg_count()))
+    });
+}
+
+fn single_prepare(c: &mut Criterion) {
+    c.bench_function("single_prepare", |b| {
+        b.iter(|| PreparedFormat::<(i32,)>::prepare(black_box("{}")).map(|p| p.arg_count()))
+    });
+    c.bench_function("single_prepare_parsed", |b| {
+        b.iter(|| PreparedFormat::<(i32,)>::prepare(black_box("{0}")).map(|p| p.arg_count()))
+    });
+}
+
+criterion_group!(benches, single_display, single_debug, single_buffer, single_prepare);
+criterion_main!(benches);
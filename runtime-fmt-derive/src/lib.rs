@@ -28,23 +28,47 @@ fn implement(ast: &syn::DeriveInput) -> quote::Tokens {
     let dummy_ident = syn::Ident::new(format!("_IMPL_FORMAT_ARGS_FOR_{}", ident));
 
     let (validate_name, validate_index, get_child, as_usize);
+    let mut get_nested = quote::Tokens::new();
     match *variant {
         syn::VariantData::Struct(ref fields) => {
             get_child = build_fields(fields);
             as_usize = build_usize(ast, fields);
-            validate_index = quote! { false };
+
+            // Fields are also accessible positionally in declaration order.
+            let len = fields.len();
+            validate_index = quote! { index < #len };
 
             let index = 0..fields.len();
             let ident: Vec<_> = fields.iter()
                 .map(|field| field.ident.as_ref().unwrap())
                 .map(ToString::to_string)
                 .collect();
+            // A dotted name such as `user.name` looks for the rest of the
+            // name in the field, if its type also implements `FormatArgs`.
+            let field = 0..fields.len();
+            let ty: Vec<_> = fields.iter().map(|field| &field.ty).collect();
+            let head = ident.clone();
             validate_name = quote! {
                 match name {
                     #(#ident => _Option::Some(#index),)*
-                    _ => _Option::None,
+                    _ => {
+                        let dot = match name.find('.') {
+                            _Option::Some(dot) => dot,
+                            _Option::None => return _Option::None,
+                        };
+                        let (len, rest) = (#len, &name[dot + 1..]);
+                        match &name[..dot] {
+                            #(
+                                #head => _runtime_fmt::codegen::nested_name::<#ty>(
+                                    len, #field, rest
+                                ),
+                            )*
+                            _ => _Option::None,
+                        }
+                    }
                 }
             };
+            get_nested = build_nested(fields);
         }
         syn::VariantData::Tuple(ref fields) => {
             get_child = build_fields(fields);
@@ -86,6 +110,7 @@ fn implement(ast: &syn::DeriveInput) -> quote::Tokens {
                 fn as_usize(index: usize) -> Option<fn(&Self) -> &usize> {
                     #as_usize
                 }
+                #get_nested
             }
         };
     }
@@ -149,3 +174,29 @@ fn build_usize(ast: &syn::DeriveInput, fields: &[syn::Field]) -> quote::Tokens {
         }
     }
 }
+
+fn build_nested(fields: &[syn::Field]) -> quote::Tokens {
+    let len = fields.len();
+    let index = 0..fields.len();
+    let ty: Vec<_> = fields.iter().map(|field| &field.ty).collect();
+    let ident: Vec<_> = fields.iter().map(|field| field.ident.as_ref().unwrap()).collect();
+    quote! {
+        fn get_nested(index: usize) -> _Option<_runtime_fmt::codegen::Nested<Self>> {
+            let (field, index) = match _runtime_fmt::codegen::split_nested(#len, index) {
+                _Option::Some(split) => split,
+                _Option::None => return _Option::None,
+            };
+            match field {
+                #(
+                    // the offset is that of the field, as `nested` requires
+                    #index => unsafe {
+                        _runtime_fmt::codegen::nested::<Self, #ty>(
+                            ::std::mem::offset_of!(Self, #ident), index
+                        )
+                    },
+                )*
+                _ => _Option::None,
+            }
+        }
+    }
+}
@@ -0,0 +1,45 @@
+//! Asynchronous output through `futures::io::AsyncWrite`.
+use std::future::Future;
+use std::io;
+use std::mem;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use alloc::vec::Vec;
+use futures_io::AsyncWrite;
+
+/// Writes already-formatted output to an `AsyncWrite` in its entirety.
+pub struct WriteAll<'a, W: ?Sized + 'a> {
+    dest: &'a mut W,
+    buf: io::Result<Vec<u8>>,
+    pos: usize,
+}
+
+impl<'a, W: ?Sized> WriteAll<'a, W> {
+    /// Prepare to write the given output, or fail with the given error when
+    /// first polled.
+    pub fn new(dest: &'a mut W, buf: io::Result<Vec<u8>>) -> Self {
+        WriteAll { dest: dest, buf: buf, pos: 0 }
+    }
+}
+
+impl<'a, W: AsyncWrite + Unpin + ?Sized> Future for WriteAll<'a, W> {
+    type Output = io::Result<()>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        let buf = match this.buf {
+            Ok(ref buf) => buf,
+            Err(_) => return Poll::Ready(mem::replace(&mut this.buf, Ok(Vec::new())).map(|_| ())),
+        };
+        while this.pos < buf.len() {
+            match Pin::new(&mut *this.dest).poll_write(cx, &buf[this.pos..]) {
+                Poll::Ready(Ok(0)) => return Poll::Ready(Err(io::Error::new(
+                    io::ErrorKind::WriteZero, "failed to write whole buffer"))),
+                Poll::Ready(Ok(n)) => this.pos += n,
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+}
@@ -0,0 +1,122 @@
+//! A per-thread cache of prepared format strings.
+//!
+//! Preparing a format string requires parsing it, which is wasted effort
+//! when the same few format strings are used over and over. The functions in
+//! this module keep a least-recently-used cache of prepared formats for each
+//! thread, keyed by the format string and the type it was prepared against.
+
+use std::any::{Any, TypeId};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use {Error, FormatArgs, PreparedFormat};
+
+/// The number of entries each thread's cache holds by default.
+pub const DEFAULT_CAPACITY: usize = 256;
+
+struct Entry {
+    last_used: u64,
+    value: Arc<dyn Any + Send + Sync>,
+}
+
+struct Cache {
+    capacity: usize,
+    len: usize,
+    clock: u64,
+    entries: HashMap<TypeId, HashMap<String, Entry>>,
+}
+
+impl Cache {
+    fn get(&mut self, ty: TypeId, spec: &str) -> Option<Arc<dyn Any + Send + Sync>> {
+        self.clock += 1;
+        let clock = self.clock;
+        self.entries.get_mut(&ty)
+            .and_then(|specs| specs.get_mut(spec))
+            .map(|entry| {
+                entry.last_used = clock;
+                entry.value.clone()
+            })
+    }
+
+    fn insert(&mut self, ty: TypeId, spec: &str, value: Arc<dyn Any + Send + Sync>) {
+        if self.capacity == 0 {
+            return
+        }
+        while self.len >= self.capacity {
+            self.evict();
+        }
+        self.clock += 1;
+        self.entries.entry(ty).or_insert_with(HashMap::new).insert(spec.to_owned(), Entry {
+            last_used: self.clock,
+            value: value,
+        });
+        self.len += 1;
+    }
+
+    // Remove the least recently used entry.
+    fn evict(&mut self) {
+        let oldest = self.entries.iter()
+            .flat_map(|(&ty, specs)| specs.iter().map(move |(spec, entry)| {
+                (entry.last_used, ty, spec)
+            }))
+            .min_by_key(|&(last_used, _, _)| last_used)
+            .map(|(_, ty, spec)| (ty, spec.clone()));
+        if let Some((ty, spec)) = oldest {
+            let specs = self.entries.get_mut(&ty).unwrap();
+            specs.remove(&spec);
+            if specs.is_empty() {
+                self.entries.remove(&ty);
+            }
+            self.len -= 1;
+        }
+    }
+}
+
+thread_local! {
+    static CACHE: RefCell<Cache> = RefCell::new(Cache {
+        capacity: DEFAULT_CAPACITY,
+        len: 0,
+        clock: 0,
+        entries: HashMap::new(),
+    });
+}
+
+/// Prepare a format string against a formattable type, reusing the result of
+/// an earlier call on this thread if one is still cached.
+///
+/// Errors are not cached, so a bad format string is parsed anew each time.
+pub fn prepared<'s, T>(spec: &'s str) -> Result<Arc<PreparedFormat<'static, T>>, Error<'s>>
+    where T: FormatArgs + 'static
+{
+    let ty = TypeId::of::<T>();
+    if let Some(value) = CACHE.with(|cache| cache.borrow_mut().get(ty, spec)) {
+        return Ok(value.downcast().expect("cache entry has the wrong type"))
+    }
+    let prepared = Arc::new(PreparedFormat::<T>::prepare(spec)?.into_owned());
+    CACHE.with(|cache| cache.borrow_mut().insert(ty, spec, prepared.clone()));
+    Ok(prepared)
+}
+
+/// Set the number of entries this thread's cache holds, evicting the least
+/// recently used entries if it currently holds more.
+///
+/// A capacity of zero disables caching.
+pub fn set_capacity(capacity: usize) {
+    CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        cache.capacity = capacity;
+        while cache.len > capacity {
+            cache.evict();
+        }
+    })
+}
+
+/// Remove every entry from this thread's cache.
+pub fn clear() {
+    CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        cache.entries.clear();
+        cache.len = 0;
+    })
+}
@@ -0,0 +1,73 @@
+//! Choice format types, which select literal text based on a value.
+//!
+//! A choice such as `{count:plural(0#no items|1#one item|other#many items)}`
+//! is a section, selected each time it is formatted by the integer its
+//! argument writes as an `Integer`.
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+use core::fmt;
+use Error;
+
+/// The integers a choice may select by, written in decimal, or not at all if
+/// they do not fit in an `i128`.
+pub trait Integer {
+    /// Write the value in decimal.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result;
+}
+
+impl<T: Copy> Integer for T where i128: TryFrom<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match i128::try_from(*self) {
+            Ok(value) => fmt::Display::fmt(&value, f),
+            Err(_) => Ok(()),
+        }
+    }
+}
+
+/// The arms of a choice, each with the value it matches or `None` for the
+/// fallback.
+pub struct Choices<'s> {
+    arms: Vec<(Option<i128>, &'s str)>,
+}
+
+/// Parse the given format type as a choice, or return `None` if it is not
+/// one.
+///
+/// The arms of a `plural(...)` are separated by `|`, and each is either
+/// `N#text` to match the integer `N`, or `other#text` or plain `text` for
+/// the fallback, which must be present. The text may contain neither `|`,
+/// `)`, nor braces.
+pub fn parse<'s>(ty: &'s str) -> Option<Result<Choices<'s>, Error<'s>>> {
+    if !ty.starts_with("plural(") || !ty.ends_with(')') {
+        return None
+    }
+    let mut arms = Vec::new();
+    for arm in ty["plural(".len()..ty.len() - 1].split('|') {
+        arms.push(match arm.find('#') {
+            None => (None, arm),
+            Some(pos) => match (&arm[..pos], &arm[pos + 1..]) {
+                ("other", text) => (None, text),
+                (key, text) => match key.parse() {
+                    Ok(key) => (Some(key), text),
+                    Err(_) => return Some(Err(Error::NoSuchFormat(ty.into()))),
+                }
+            }
+        });
+    }
+    if !arms.iter().any(|arm| arm.0.is_none()) {
+        return Some(Err(Error::NoSuchFormat(ty.into())))
+    }
+    Some(Ok(Choices { arms: arms }))
+}
+
+impl<'s> Choices<'s> {
+    /// Select the text of the first arm matching the given value, falling
+    /// back to the first fallback arm, which is also taken for no value.
+    pub fn select(&self, value: Option<i128>) -> &'s str {
+        self.arms.iter()
+            .find(|arm| arm.0.is_some() && arm.0 == value)
+            .or_else(|| self.arms.iter().find(|arm| arm.0.is_none()))
+            .map(|arm| arm.1)
+            .unwrap()
+    }
+}
@@ -1,8 +1,11 @@
 //! Support for the codegen module.
 #![doc(hidden)]
 
-use std::mem::{size_of, zeroed};
-use std::fmt::*;
+use core::marker::PhantomData;
+use core::mem::{size_of, zeroed};
+use core::fmt::*;
+use choice::Integer;
+use custom::{Base64, Grouped, Hex};
 
 /// Implementors correspond to formatting traits which may apply to values.
 pub trait FormatTrait {
@@ -59,7 +62,7 @@ macro_rules! impl_format_trait {
 
 impl_format_trait! {
     Display, Debug, LowerExp, UpperExp, Octal, Pointer, Binary, LowerHex,
-    UpperHex,
+    UpperHex, Grouped, Hex, Base64, Integer,
 }
 
 #[inline]
@@ -144,11 +147,23 @@ pub fn as_usize<A, B>(f: fn(&A) -> &B) -> Option<fn(&A) -> &usize> {
 ///
 /// Implementations may be generated automatically using `runtime-fmt-derive`
 /// and `#[derive(FormatArgs)]`.
+///
+/// Arrays of up to 32 elements and tuples of up to 12 implement this trait,
+/// exposing each element by position.
+///
+/// Because names and indices are checked without a value at hand, only types
+/// whose shape is fixed at compile time can implement this trait. Maps such
+/// as `HashMap` and `BTreeMap`, whose keys are only known at runtime, should
+/// instead be prepared against with `map::MapFormat`, or formatted by way of
+/// `Param::from_map` and `FormatBuf`. Slices, whose length `validate_index`
+/// cannot see, should be formatted by way of `Param::from_slice`.
 pub trait FormatArgs {
     /// Find the index within this type corresponding to the provided name.
     ///
     /// If this function returns `Some`, `get_child` with the returned index
-    /// must not panic.
+    /// must not panic. Names and indices share one index space: a name which
+    /// returns the index `0` refers to the same child as `{0}`, and the two
+    /// are formatted from a single argument.
     fn validate_name(name: &str) -> Option<usize>;
 
     /// Validate that a given index is within range for this type.
@@ -168,5 +183,287 @@ pub trait FormatArgs {
     ///
     /// Returns `None` if the child at the given index cannot be interpreted
     /// as a `usize`. Panics if the index is invalid.
+    ///
+    /// Counts are read by reference at formatting time, so only children
+    /// which are themselves `usize` qualify. `Param`, which converts when it
+    /// is created, also accepts other integer types and `NonZeroUsize`.
     fn as_usize(index: usize) -> Option<fn(&Self) -> &usize>;
+
+    /// Return where to find the child for an index which `validate_name`
+    /// returned for a dotted name such as `user.name`, or `None` for any
+    /// other index.
+    ///
+    /// Dotted names are an extension of the names `std::fmt` allows. Such an
+    /// index need not be valid for `validate_index`, `get_child` or
+    /// `as_usize`, which are not called with it.
+    #[inline]
+    fn get_nested(_: usize) -> Option<Nested<Self>> {
+        None
+    }
+}
+
+/// Names a lifetime which an `impl Trait` return type borrows from, where
+/// it would otherwise not be allowed to.
+pub trait Captures<'a> {}
+
+impl<'a, T: ?Sized> Captures<'a> for T {}
+
+/// Stands in for the type of a nested child once it has been erased.
+pub struct Opaque {
+    _private: [u8; 0],
+}
+
+/// A child reached through a dotted name: a value of some type implementing
+/// `FormatArgs`, found at a byte offset within its parent, and the index
+/// within that value which the rest of the name refers to.
+///
+/// The offset is trusted when formatting, so a `Nested` may only be made by
+/// the unsafe `nested`, and only describes a value within the parent `P` it
+/// was made for.
+pub struct Nested<P: ?Sized> {
+    offset: usize,
+    index: usize,
+    child: ChildArgs,
+    parent: PhantomData<fn(&P)>,
+}
+
+impl<P: ?Sized> Clone for Nested<P> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<P: ?Sized> Copy for Nested<P> {}
+
+impl<P: ?Sized> Nested<P> {
+    /// The byte offset of the value within its parent.
+    #[inline]
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// The index within the value.
+    #[inline]
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// The `FormatArgs` implementation of the value.
+    #[inline]
+    pub fn child(&self) -> ChildArgs {
+        self.child
+    }
+}
+
+// Finds the formatting function for a format type, as `erase` does.
+type ChildFn = for<'n> fn(&'n str, usize) -> ::core::result::Result<FormatFn<Opaque>, ::Error<'n>>;
+
+/// The `FormatArgs` implementation of a nested child, with its type erased.
+#[derive(Clone, Copy)]
+pub struct ChildArgs {
+    /// As `FormatArgs::validate_index`.
+    pub validate_index: fn(usize) -> bool,
+    /// As `FormatArgs::get_child`, choosing the format trait by format type.
+    pub get_child: ChildFn,
+    /// As `FormatArgs::as_usize`.
+    pub as_usize: fn(usize) -> Option<fn(&Opaque) -> &usize>,
+    /// As `FormatArgs::get_nested`.
+    pub get_nested: fn(usize) -> Option<Nested<Opaque>>,
+}
+
+// Specialization abuse to look into children only when they implement
+// `FormatArgs`, so that fields of any type may be derived.
+trait SpecNested {
+    fn validate_name(name: &str) -> Option<usize>;
+    fn child_args() -> Option<ChildArgs>;
+}
+
+impl<B> SpecNested for B {
+    default fn validate_name(_: &str) -> Option<usize> { None }
+    default fn child_args() -> Option<ChildArgs> { None }
+}
+
+impl<B: FormatArgs> SpecNested for B {
+    fn validate_name(name: &str) -> Option<usize> { B::validate_name(name) }
+    fn child_args() -> Option<ChildArgs> {
+        Some(ChildArgs {
+            validate_index: B::validate_index,
+            get_child: erased_child::<B>,
+            as_usize: erased_usize::<B>,
+            get_nested: erased_nested::<B>,
+        })
+    }
+}
+
+fn erased_child<'n, B: FormatArgs>(name: &'n str, index: usize)
+    -> ::core::result::Result<FormatFn<Opaque>, ::Error<'n>>
+{
+    // The same erasure as `ArgumentV1::new`, which `Opaque` is only ever
+    // given to in place of a `B`.
+    ::erase::codegen_get_child::<B>(name, index).map(|f| unsafe {
+        ::core::mem::transmute::<FormatFn<B>, FormatFn<Opaque>>(f)
+    })
+}
+
+fn erased_nested<B: FormatArgs>(index: usize) -> Option<Nested<Opaque>> {
+    // `Opaque` is only ever found in place of a `B`.
+    B::get_nested(index).map(|found| Nested {
+        offset: found.offset,
+        index: found.index,
+        child: found.child,
+        parent: PhantomData,
+    })
+}
+
+fn erased_usize<B: FormatArgs>(index: usize) -> Option<fn(&Opaque) -> &usize> {
+    B::as_usize(index).map(|f| unsafe {
+        ::core::mem::transmute::<fn(&B) -> &usize, fn(&Opaque) -> &usize>(f)
+    })
+}
+
+/// Return the index for the rest of a dotted name within the field at
+/// `field` of a type with `len` fields, whose type is `B`.
+///
+/// Returns `None` if `B` does not implement `FormatArgs` or does not have
+/// the name. The index is past those of the fields themselves, and is taken
+/// apart again by `split_nested`.
+#[inline]
+pub fn nested_name<B>(len: usize, field: usize, rest: &str) -> Option<usize> {
+    let index = <B as SpecNested>::validate_name(rest)?;
+    index.checked_mul(len)?.checked_add(len)?.checked_add(field)
+}
+
+/// Split an index returned by `nested_name` into the field it is within
+/// and the index within that field.
+#[inline]
+pub fn split_nested(len: usize, index: usize) -> Option<(usize, usize)> {
+    if len == 0 || index < len {
+        None
+    } else {
+        Some(((index - len) % len, (index - len) / len))
+    }
+}
+
+/// Describe the child at `index` within a field of type `B` at byte offset
+/// `offset`, for `FormatArgs::get_nested`.
+///
+/// # Safety
+///
+/// `offset` must be that of a field of type `B` within `P`, as given by
+/// `offset_of!`. The field is read through it without further checks.
+#[doc(hidden)]
+#[inline]
+pub unsafe fn nested<P: ?Sized, B>(offset: usize, index: usize) -> Option<Nested<P>> {
+    <B as SpecNested>::child_args().map(|child| Nested {
+        offset: offset,
+        index: index,
+        child: child,
+        parent: PhantomData,
+    })
+}
+
+// Each element needs a zero-sized accessor of its own, so the indices are
+// spelled out up to the largest supported length.
+macro_rules! array_child {
+    ($f:ident, $index:ident, $($k:expr)*) => {
+        match $index {
+            $($k => combine::<$f, Self, T, _>(|this| &this[..][$k]),)*
+            _ => panic!("bad index {}", $index),
+        }
+    }
+}
+
+macro_rules! array_usize {
+    ($index:ident, $($k:expr)*) => {
+        match $index {
+            $($k => {
+                fn inner<'a, T>(this: &'a [T; LEN]) -> &'a T { &this[..][$k] }
+                as_usize(inner::<T>)
+            })*
+            _ => panic!("bad index {}", $index),
+        }
+    }
+}
+
+macro_rules! array_format_args {
+    ($($n:expr)*) => {$(
+        /// Elements are available by position only.
+        impl<T> FormatArgs for [T; $n] {
+            #[inline]
+            fn validate_name(_: &str) -> Option<usize> {
+                None
+            }
+
+            #[inline]
+            #[allow(unused_comparisons)]
+            fn validate_index(index: usize) -> bool {
+                index < $n
+            }
+
+            fn get_child<F: FormatTrait + ?Sized>(index: usize) -> Option<FormatFn<Self>> {
+                array_child!(F, index, 0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15
+                             16 17 18 19 20 21 22 23 24 25 26 27 28 29 30 31)
+            }
+
+            fn as_usize(index: usize) -> Option<fn(&Self) -> &usize> {
+                const LEN: usize = $n;
+                array_usize!(index, 0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15
+                             16 17 18 19 20 21 22 23 24 25 26 27 28 29 30 31)
+            }
+        }
+    )*}
+}
+
+array_format_args! {
+    0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16
+    17 18 19 20 21 22 23 24 25 26 27 28 29 30 31 32
+}
+
+macro_rules! tuple_format_args {
+    ($(($len:expr; $($k:tt $t:ident)*))*) => {$(
+        /// Elements are available by position only.
+        impl<$($t),*> FormatArgs for ($($t,)*) {
+            #[inline]
+            fn validate_name(_: &str) -> Option<usize> {
+                None
+            }
+
+            #[inline]
+            fn validate_index(index: usize) -> bool {
+                index < $len
+            }
+
+            fn get_child<F: FormatTrait + ?Sized>(index: usize) -> Option<FormatFn<Self>> {
+                match index {
+                    $($k => combine::<F, Self, $t, _>(|this| &this.$k),)*
+                    _ => panic!("bad index {}", index),
+                }
+            }
+
+            fn as_usize(index: usize) -> Option<fn(&Self) -> &usize> {
+                match index {
+                    $($k => {
+                        let get: fn(&Self) -> &$t = |this| &this.$k;
+                        as_usize(get)
+                    })*
+                    _ => panic!("bad index {}", index),
+                }
+            }
+        }
+    )*}
+}
+
+tuple_format_args! {
+    (1; 0 T0)
+    (2; 0 T0 1 T1)
+    (3; 0 T0 1 T1 2 T2)
+    (4; 0 T0 1 T1 2 T2 3 T3)
+    (5; 0 T0 1 T1 2 T2 3 T3 4 T4)
+    (6; 0 T0 1 T1 2 T2 3 T3 4 T4 5 T5)
+    (7; 0 T0 1 T1 2 T2 3 T3 4 T4 5 T5 6 T6)
+    (8; 0 T0 1 T1 2 T2 3 T3 4 T4 5 T5 6 T6 7 T7)
+    (9; 0 T0 1 T1 2 T2 3 T3 4 T4 5 T5 6 T6 7 T7 8 T8)
+    (10; 0 T0 1 T1 2 T2 3 T3 4 T4 5 T5 6 T6 7 T7 8 T8 9 T9)
+    (11; 0 T0 1 T1 2 T2 3 T3 4 T4 5 T5 6 T6 7 T7 8 T8 9 T9 10 T10)
+    (12; 0 T0 1 T1 2 T2 3 T3 4 T4 5 T5 6 T6 7 T7 8 T8 9 T9 10 T10 11 T11)
 }
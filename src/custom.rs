@@ -0,0 +1,280 @@
+//! Formatting traits beyond those provided by `std::fmt`.
+//!
+//! Each trait here is selected by its own format type, just as `{:x}`
+//! selects `LowerHex`, and may be implemented for types outside this crate.
+//! Types may also name format types of their own through `CustomFormat`.
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::{self, Write};
+use core::fmt::rt::v1;
+use core::str;
+use erase::{options, render};
+use ArgumentV1;
+
+/// Format trait for `{:grp}`: an integer with its digits in groups of three.
+///
+/// The separator is `,` unless a fill character is given, so `{:_<grp}`
+/// groups with underscores. Width and sign flags apply as for `Display`,
+/// and any padding likewise uses the fill character.
+pub trait Grouped {
+    /// Formats the value using the given formatter.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result;
+}
+
+/// Format trait for `{:hex}`: bytes as pairs of lowercase hex digits.
+///
+/// With the alternate flag, as in `{:#hex}`, each byte is prefixed with `0x`
+/// and separated from the next by a space. Width and precision are ignored.
+pub trait Hex {
+    /// Formats the value using the given formatter.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result;
+}
+
+/// Format trait for `{:b64}`: bytes in padded standard base64.
+///
+/// Width and precision are ignored.
+pub trait Base64 {
+    /// Formats the value using the given formatter.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result;
+}
+
+/// A type with format types of its own, such as `{:rgb}` for a color.
+///
+/// Format types which are not otherwise recognized, or whose built-in trait
+/// the type does not implement, are looked up here for parameters to
+/// `FormatBuf` and the `rt_` macros. They are not available through
+/// `FormatArgs`, whose formatting functions are chosen by trait rather than
+/// by name.
+pub trait CustomFormat {
+    /// Return the function for the named format type, if this type has one.
+    fn custom_format(name: &str) -> Option<fn(&Self, &mut fmt::Formatter) -> fmt::Result>;
+}
+
+// Enough for the 39 digits of `u128::MAX` and 12 four-byte separators.
+const GROUPED_LEN: usize = 96;
+
+fn fmt_grouped(nonneg: bool, mut n: u128, f: &mut fmt::Formatter) -> fmt::Result {
+    let sep = match f.fill() {
+        ' ' => ',',
+        c => c,
+    };
+    let mut sep_buf = [0; 4];
+    let sep = sep.encode_utf8(&mut sep_buf).as_bytes();
+
+    let mut buf = [0; GROUPED_LEN];
+    let mut pos = buf.len();
+    let mut digits = 0;
+    loop {
+        if digits != 0 && digits % 3 == 0 {
+            pos -= sep.len();
+            buf[pos..pos + sep.len()].copy_from_slice(sep);
+        }
+        pos -= 1;
+        buf[pos] = b'0' + (n % 10) as u8;
+        digits += 1;
+        n /= 10;
+        if n == 0 {
+            break
+        }
+    }
+    // only ASCII digits and whole UTF-8 separators were written
+    let grouped = unsafe { str::from_utf8_unchecked(&buf[pos..]) };
+    f.pad_integral(nonneg, "", grouped)
+}
+
+macro_rules! grouped {
+    (unsigned $($t:ty)*) => {$(
+        impl Grouped for $t {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                fmt_grouped(true, *self as u128, f)
+            }
+        }
+    )*};
+    (signed $($t:ty)*) => {$(
+        impl Grouped for $t {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                let n = *self as i128;
+                let abs = if n < 0 { (n as u128).wrapping_neg() } else { n as u128 };
+                fmt_grouped(n >= 0, abs, f)
+            }
+        }
+    )*};
+}
+
+grouped!(unsigned u8 u16 u32 u64 u128 usize);
+grouped!(signed i8 i16 i32 i64 i128 isize);
+
+impl<'a, T: Grouped + ?Sized> Grouped for &'a T {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        (**self).fmt(f)
+    }
+}
+
+const HEX_DIGITS: &[u8; 16] = b"0123456789abcdef";
+
+impl Hex for [u8] {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let alternate = f.alternate();
+        for (i, &byte) in self.iter().enumerate() {
+            if alternate {
+                f.write_str(if i == 0 { "0x" } else { " 0x" })?;
+            }
+            let pair = [HEX_DIGITS[(byte >> 4) as usize], HEX_DIGITS[(byte & 0xf) as usize]];
+            // only ASCII digits were written
+            f.write_str(unsafe { str::from_utf8_unchecked(&pair) })?;
+        }
+        Ok(())
+    }
+}
+
+const BASE64_DIGITS: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+impl Base64 for [u8] {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for chunk in self.chunks(3) {
+            let n = chunk.iter().enumerate()
+                .fold(0u32, |n, (i, &byte)| n | (byte as u32) << (16 - 8 * i));
+            let mut quad = [b'='; 4];
+            for i in 0..chunk.len() + 1 {
+                quad[i] = BASE64_DIGITS[(n >> (18 - 6 * i) & 0x3f) as usize];
+            }
+            // only ASCII digits and padding were written
+            f.write_str(unsafe { str::from_utf8_unchecked(&quad) })?;
+        }
+        Ok(())
+    }
+}
+
+macro_rules! bytes {
+    ($($name:ident)*) => {$(
+        impl $name for Vec<u8> {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                $name::fmt(&self[..], f)
+            }
+        }
+
+        impl<'a, T: $name + ?Sized> $name for &'a T {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                (**self).fmt(f)
+            }
+        }
+    )*};
+}
+
+bytes!(Hex Base64);
+
+/// The separators used to write numbers in some locale.
+///
+/// The default is `.` between the integer and fractional parts and `,`
+/// between groups of digits, as in English.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Locale {
+    /// Separates the integer part of a number from its fractional part.
+    pub decimal: char,
+    /// Separates groups of three digits in the integer part, for `{:grp}`.
+    pub group: char,
+}
+
+impl Default for Locale {
+    fn default() -> Self {
+        Locale { decimal: '.', group: ',' }
+    }
+}
+
+/// A number to be formatted with the separators of a locale.
+///
+/// `Display`, `LowerExp` and `UpperExp` write the number as usual but with
+/// the locale's decimal separator, leaving the exponent and values such as
+/// `NaN` and `inf` alone. `Grouped` is also implemented for any `Display`
+/// number, floats included, grouping the integer part with the locale's
+/// separator rather than the fill character. Only the values wrapped are
+/// affected, so other arguments to the same format are not touched:
+///
+/// ```rust
+/// # #[macro_use] extern crate runtime_fmt;
+/// use runtime_fmt::custom::{Locale, Localized};
+/// # fn main() {
+/// let german = Locale { decimal: ',', group: '.' };
+/// let s = rt_format!("{:.2} {} {:grp}", Localized(3.14159, german), 1.5,
+///                    Localized(1234567.5, german)).unwrap();
+/// assert_eq!(s, "3,14 1.5 1.234.567,5");
+/// # }
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Localized<T>(pub T, pub Locale);
+
+// Format a value with the given options rather than those of a formatter.
+fn format_with<T>(value: &T, func: fn(&T, &mut fmt::Formatter) -> fmt::Result,
+                  format: v1::FormatSpec) -> Result<String, fmt::Error> {
+    render(ArgumentV1::new(value, func), format)
+}
+
+// The position of the decimal point, which is the only `.` with a digit on
+// either side, since padding never falls between digits.
+fn decimal_point(text: &str) -> Option<usize> {
+    let bytes = text.as_bytes();
+    (1..bytes.len().saturating_sub(1)).find(|&i| {
+        bytes[i] == b'.' && bytes[i - 1].is_ascii_digit() && bytes[i + 1].is_ascii_digit()
+    })
+}
+
+fn fmt_localized<T>(value: &T, func: fn(&T, &mut fmt::Formatter) -> fmt::Result,
+                    locale: Locale, f: &mut fmt::Formatter) -> fmt::Result {
+    let text = format_with(value, func, options(f))?;
+    match decimal_point(&text) {
+        Some(i) => {
+            f.write_str(&text[..i])?;
+            f.write_char(locale.decimal)?;
+            f.write_str(&text[i + 1..])
+        }
+        None => f.write_str(&text),
+    }
+}
+
+macro_rules! localized {
+    ($($name:ident)*) => {$(
+        impl<T: fmt::$name> fmt::$name for Localized<T> {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                fmt_localized(&self.0, <T as fmt::$name>::fmt, self.1, f)
+            }
+        }
+    )*};
+}
+
+localized!(Display LowerExp UpperExp);
+
+impl<T: fmt::Display> Grouped for Localized<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // The sign and padding are left to `pad_integral`, as for integers.
+        let text = format_with(&self.0, <T as fmt::Display>::fmt, v1::FormatSpec {
+            fill: ' ',
+            align: v1::Alignment::Unknown,
+            flags: 0,
+            precision: f.precision().map_or(v1::Count::Implied, v1::Count::Is),
+            width: v1::Count::Implied,
+        })?;
+        if text == "NaN" {
+            // never signed, so let the value pad itself
+            return fmt::Display::fmt(&self.0, f)
+        }
+        let nonneg = !text.starts_with('-');
+        let text = if nonneg { &text[..] } else { &text[1..] };
+        let digits = text.bytes().take_while(u8::is_ascii_digit).count();
+        let mut grouped = String::with_capacity(text.len() + digits / 3 * 4);
+        for (i, c) in text[..digits].chars().enumerate() {
+            if i != 0 && (digits - i) % 3 == 0 {
+                grouped.push(self.1.group);
+            }
+            grouped.push(c);
+        }
+        match decimal_point(text) {
+            Some(i) => {
+                grouped.push(self.1.decimal);
+                grouped.push_str(&text[i + 1..]);
+            }
+            None => grouped.push_str(&text[digits..]),
+        }
+        f.pad_integral(nonneg, "", &grouped)
+    }
+}
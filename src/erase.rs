@@ -1,26 +1,76 @@
 //! Type erasure for formattable types.
-use std::fmt;
-use std::convert::TryFrom;
-use Error;
+use alloc::string::String;
+use core::fmt::{self, Write};
+use core::fmt::rt::v1;
+use core::convert::TryFrom;
+use choice;
+use custom;
+use section;
+use {Arguments, ArgumentV1, CountError, Error};
 
 type Func<T> = fn(&T, &mut fmt::Formatter) -> fmt::Result;
 
 trait AsUsize {
-    fn as_usize(&self) -> Option<usize>;
+    fn as_usize(&self) -> Result<usize, CountError>;
 }
 impl<T> AsUsize for T {
     #[inline]
-    default fn as_usize(&self) -> Option<usize> { None }
+    default fn as_usize(&self) -> Result<usize, CountError> { Err(CountError::WrongType) }
 }
 impl<T> AsUsize for T where usize: TryFrom<T>, T: Copy {
     #[inline]
-    fn as_usize(&self) -> Option<usize> {
-        usize::try_from(*self).ok()
+    fn as_usize(&self) -> Result<usize, CountError> {
+        usize::try_from(*self).map_err(|_| match AsI128::as_i128(self) {
+            Some(n) if n < 0 => CountError::Negative,
+            _ => CountError::Overflow,
+        })
+    }
+}
+
+trait AsI128 {
+    fn as_i128(&self) -> Option<i128>;
+}
+impl<T> AsI128 for T {
+    #[inline]
+    default fn as_i128(&self) -> Option<i128> { None }
+}
+impl<T> AsI128 for T where i128: TryFrom<T>, T: Copy {
+    #[inline]
+    fn as_i128(&self) -> Option<i128> {
+        i128::try_from(*self).ok()
+    }
+}
+
+trait AsInteger {
+    fn integer() -> Option<Func<Self>>;
+}
+impl<T> AsInteger for T {
+    #[inline]
+    default fn integer() -> Option<Func<Self>> { None }
+}
+impl<T: choice::Integer> AsInteger for T {
+    #[inline]
+    fn integer() -> Option<Func<Self>> {
+        Some(<Self as choice::Integer>::fmt)
+    }
+}
+
+trait AsCustom {
+    fn custom(name: &str) -> Option<Func<Self>>;
+}
+impl<T> AsCustom for T {
+    #[inline]
+    default fn custom(_: &str) -> Option<Func<Self>> { None }
+}
+impl<T: custom::CustomFormat> AsCustom for T {
+    #[inline]
+    fn custom(name: &str) -> Option<Func<Self>> {
+        T::custom_format(name)
     }
 }
 
 macro_rules! traits {
-    ($($string:pat, $upper:ident, $lower:ident;)*) => {
+    ($($string:tt, $module:ident::$upper:ident, $lower:ident;)*) => {
         $(
             trait $upper {
                 fn $lower() -> Option<Func<Self>>;
@@ -29,28 +79,47 @@ macro_rules! traits {
                 #[inline]
                 default fn $lower() -> Option<Func<Self>> { None }
             }
-            impl<T: fmt::$upper> $upper for T {
+            impl<T: $module::$upper> $upper for T {
                 #[inline]
                 fn $lower() -> Option<Func<Self>> {
-                    Some(<Self as fmt::$upper>::fmt)
+                    Some(<Self as $module::$upper>::fmt)
                 }
             }
         )*
 
+        /// The built-in format types, in the order they are documented.
+        pub const FORMAT_TYPES: &[&str] = &[$($string),*];
+
+        /// The trait a built-in format type requires of its argument.
+        pub fn trait_name(ty: &str) -> Option<&'static str> {
+            match ty {
+                $($string => Some(stringify!($upper)),)*
+                _ => None,
+            }
+        }
+
         pub trait Format {
-            fn as_usize(&self) -> Option<usize>;
+            fn as_usize(&self) -> Result<usize, CountError>;
+            fn type_name(&self) -> &'static str;
             fn by_name<'n>(&self, name: &'n str, idx: usize) -> Result<fmt::ArgumentV1, Error<'n>>;
         }
 
         impl<T> Format for T {
             #[inline]
-            fn as_usize(&self) -> Option<usize> {
+            fn as_usize(&self) -> Result<usize, CountError> {
                 AsUsize::as_usize(self)
             }
+            #[inline]
+            fn type_name(&self) -> &'static str {
+                ::core::any::type_name::<T>()
+            }
             fn by_name<'n>(&self, name: &'n str, idx: usize) -> Result<fmt::ArgumentV1, Error<'n>> {
+                let name = section::argument_type(name);
                 match name {
                     $(
-                        $string => match <Self as $upper>::$lower() {
+                        $string => match <Self as $upper>::$lower()
+                            .or_else(|| <Self as AsCustom>::custom(name))
+                        {
                             Some(f) => Ok(fmt::ArgumentV1::new(self, f)),
                             None => Err(Error::UnsatisfiedFormat {
                                 idx: idx,
@@ -58,7 +127,17 @@ macro_rules! traits {
                             }),
                         },
                     )*
-                    _ => Err(Error::NoSuchFormat(name)),
+                    section::INTEGER => match <Self as AsInteger>::integer() {
+                        Some(f) => Ok(fmt::ArgumentV1::new(self, f)),
+                        None => Err(Error::UnsatisfiedFormat {
+                            idx: idx,
+                            must_implement: "Into<i128>",
+                        }),
+                    },
+                    _ => match <Self as AsCustom>::custom(name) {
+                        Some(f) => Ok(fmt::ArgumentV1::new(self, f)),
+                        None => Err(Error::NoSuchFormat(name.into())),
+                    },
                 }
             }
         }
@@ -66,9 +145,10 @@ macro_rules! traits {
         pub fn codegen_get_child<'n, T: ::FormatArgs>(name: &'n str, idx: usize)
             -> Result<fn(&T, &mut fmt::Formatter) -> fmt::Result, Error>
         {
+            let name = section::argument_type(name);
             match name {
                 $(
-                    $string => match T::get_child::<dyn fmt::$upper>(idx) {
+                    $string => match T::get_child::<dyn $module::$upper>(idx) {
                         Some(f) => Ok(f),
                         None => Err(Error::UnsatisfiedFormat {
                             idx: idx,
@@ -76,20 +156,58 @@ macro_rules! traits {
                         })
                     },
                 )*
-                _ => Err(Error::NoSuchFormat(name)),
+                section::INTEGER => match T::get_child::<dyn choice::Integer>(idx) {
+                    Some(f) => Ok(f),
+                    None => Err(Error::UnsatisfiedFormat {
+                        idx: idx,
+                        must_implement: "Into<i128>",
+                    })
+                },
+                _ => Err(Error::NoSuchFormat(name.into())),
             }
         }
     }
 }
 
 traits! {
-    "", Display, display;
-    "?", Debug, debug;
-    "e", LowerExp, lower_exp;
-    "E", UpperExp, upper_exp;
-    "o", Octal, octal;
-    "p", Pointer, pointer;
-    "b", Binary, binary;
-    "x", LowerHex, lower_hex;
-    "X", UpperHex, upper_hex;
+    "", fmt::Display, display;
+    "?", fmt::Debug, debug;
+    "e", fmt::LowerExp, lower_exp;
+    "E", fmt::UpperExp, upper_exp;
+    "o", fmt::Octal, octal;
+    "p", fmt::Pointer, pointer;
+    "b", fmt::Binary, binary;
+    "x", fmt::LowerHex, lower_hex;
+    "X", fmt::UpperHex, upper_hex;
+    "grp", custom::Grouped, grouped;
+    "hex", custom::Hex, hex;
+    "b64", custom::Base64, base64;
+}
+
+// The options of a formatter, for formatting again elsewhere.
+pub fn options(f: &fmt::Formatter) -> v1::FormatSpec {
+    let flag = |set: bool, flag: u32| if set { 1 << flag } else { 0 };
+    v1::FormatSpec {
+        fill: f.fill(),
+        align: match f.align() {
+            Some(fmt::Alignment::Left) => v1::Alignment::Left,
+            Some(fmt::Alignment::Right) => v1::Alignment::Right,
+            Some(fmt::Alignment::Center) => v1::Alignment::Center,
+            None => v1::Alignment::Unknown,
+        },
+        flags: flag(f.sign_plus(), 0) | flag(f.sign_minus(), 1) | flag(f.alternate(), 2) |
+            flag(f.sign_aware_zero_pad(), 3),
+        precision: f.precision().map_or(v1::Count::Implied, v1::Count::Is),
+        width: f.width().map_or(v1::Count::Implied, v1::Count::Is),
+    }
+}
+
+// Format an argument alone with the given options.
+pub fn render(arg: ArgumentV1, format: v1::FormatSpec) -> Result<String, fmt::Error> {
+    let alone = [v1::Argument { position: 0, format: format }];
+    let arg = [arg];
+    let args = Arguments::new_v1_formatted(&[""], &arg, &alone);
+    let mut text = String::new();
+    text.write_fmt(args)?;
+    Ok(text)
 }
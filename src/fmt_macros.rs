@@ -32,9 +32,12 @@ pub use self::Alignment::*;
 pub use self::Flag::*;
 pub use self::Count::*;
 
-use std::str;
-use std::string;
-use std::iter;
+use core::str;
+use core::iter;
+use core::ops::Range;
+use alloc::borrow::ToOwned;
+use alloc::string;
+use alloc::vec::Vec;
 
 use self::unicode_xid::UnicodeXID;
 
@@ -56,6 +59,9 @@ pub struct Argument<'a> {
     pub position: Position<'a>,
     /// How to format the argument
     pub format: FormatSpec<'a>,
+    /// For a conditional section such as `{name?prefix}`, the text to write
+    /// before the argument when it is not blank
+    pub prefix: Option<&'a str>,
 }
 
 /// Specification for the formatting of an argument in the format string.
@@ -138,40 +144,51 @@ pub enum Count<'a> {
 pub struct Parser<'a> {
     input: &'a str,
     cur: iter::Peekable<str::CharIndices<'a>>,
-    /// Error messages accumulated during parsing
-    pub errors: Vec<(string::String, Option<string::String>)>,
+    /// Error messages accumulated during parsing, with the byte range of the
+    /// input to which each applies
+    pub errors: Vec<(string::String, Option<string::String>, Range<usize>)>,
     /// Current position of implicit positional argument pointer
     curarg: usize,
+    /// The characters which open and close an argument, usually braces
+    open: char,
+    close: char,
 }
 
 impl<'a> Iterator for Parser<'a> {
     type Item = Piece<'a>;
 
     fn next(&mut self) -> Option<Piece<'a>> {
+        let (open, close) = (self.open, self.close);
         if let Some(&(pos, c)) = self.cur.peek() {
-            match c {
-                '{' => {
-                    self.cur.next();
-                    if self.consume('{') {
-                        Some(String(self.string(pos + 1)))
+            if c == open {
+                self.cur.next();
+                if self.consume(open) {
+                    Some(String(self.string(pos + open.len_utf8())))
+                } else {
+                    let ret = Some(NextArgument(self.argument()));
+                    self.ws();
+                    if self.cur.peek().is_none() {
+                        let msg = &format!("unterminated format specifier at position {}", pos);
+                        let end = self.input.len();
+                        let note = self.escape_note(open);
+                        self.err_with_note(msg, &note, end..end);
                     } else {
-                        let ret = Some(NextArgument(self.argument()));
-                        self.must_consume('}');
-                        ret
+                        self.must_consume(close);
                     }
+                    ret
                 }
-                '}' => {
-                    self.cur.next();
-                    if self.consume('}') {
-                        Some(String(self.string(pos + 1)))
-                    } else {
-                        self.err_with_note("unmatched `}` found",
-                                           "if you intended to print `}`, \
-                                           you can escape it using `}}`");
-                        None
-                    }
+            } else if c == close {
+                self.cur.next();
+                if self.consume(close) {
+                    Some(String(self.string(pos + close.len_utf8())))
+                } else {
+                    let msg = &format!("unmatched `{}` found", close);
+                    let note = self.escape_note(close);
+                    self.err_with_note(msg, &note, pos..pos + close.len_utf8());
+                    None
                 }
-                _ => Some(String(self.string(pos))),
+            } else {
+                Some(String(self.string(pos)))
             }
         } else {
             None
@@ -182,26 +199,39 @@ impl<'a> Iterator for Parser<'a> {
 impl<'a> Parser<'a> {
     /// Creates a new parser for the given format string
     pub fn new(s: &'a str) -> Parser<'a> {
+        Parser::with_delims(s, '{', '}')
+    }
+
+    /// Creates a new parser for a format string whose arguments are opened
+    /// and closed by the given characters rather than by braces
+    pub fn with_delims(s: &'a str, open: char, close: char) -> Parser<'a> {
         Parser {
             input: s,
             cur: s.char_indices().peekable(),
             errors: vec![],
             curarg: 0,
+            open: open,
+            close: close,
         }
     }
 
+    /// The note suggesting how to print a delimiter literally.
+    fn escape_note(&self, c: char) -> string::String {
+        format!("if you intended to print `{}`, you can escape it using `{}{}`", c, c, c)
+    }
+
     /// Notifies of an error. The message doesn't actually need to be of type
     /// String, but I think it does when this eventually uses conditions so it
     /// might as well start using it now.
-    fn err(&mut self, msg: &str) {
-        self.errors.push((msg.to_owned(), None));
+    fn err(&mut self, msg: &str, span: Range<usize>) {
+        self.errors.push((msg.to_owned(), None, span));
     }
 
     /// Notifies of an error. The message doesn't actually need to be of type
     /// String, but I think it does when this eventually uses conditions so it
     /// might as well start using it now.
-    fn err_with_note(&mut self, msg: &str, note: &str) {
-        self.errors.push((msg.to_owned(), Some(note.to_owned())));
+    fn err_with_note(&mut self, msg: &str, note: &str, span: Range<usize>) {
+        self.errors.push((msg.to_owned(), Some(note.to_owned()), span));
     }
 
     /// Optionally consumes the specified character. If the character is not at
@@ -224,19 +254,27 @@ impl<'a> Parser<'a> {
     /// found, an error is emitted.
     fn must_consume(&mut self, c: char) {
         self.ws();
-        if let Some(&(_, maybe)) = self.cur.peek() {
+        if let Some(&(pos, maybe)) = self.cur.peek() {
             if c == maybe {
                 self.cur.next();
             } else {
-                self.err(&format!("expected `{:?}`, found `{:?}`", c, maybe));
+                let msg = &format!("expected `{:?}`, found `{:?}`", c, maybe);
+                let span = pos..pos + maybe.len_utf8();
+                if c == self.close {
+                    let note = self.escape_note(self.open);
+                    self.err_with_note(msg, &note, span);
+                } else {
+                    self.err(msg, span);
+                }
             }
         } else {
             let msg = &format!("expected `{:?}` but string was terminated", c);
-            if c == '}' {
-                self.err_with_note(msg,
-                                   "if you intended to print `{`, you can escape it using `{{`");
+            let end = self.input.len();
+            if c == self.close {
+                let note = self.escape_note(self.open);
+                self.err_with_note(msg, &note, end..end);
             } else {
-                self.err(msg);
+                self.err(msg, end..end);
             }
         }
     }
@@ -258,14 +296,10 @@ impl<'a> Parser<'a> {
     fn string(&mut self, start: usize) -> &'a str {
         // we may not consume the character, peek the iterator
         while let Some(&(pos, c)) = self.cur.peek() {
-            match c {
-                '{' | '}' => {
-                    return &self.input[start..pos];
-                }
-                _ => {
-                    self.cur.next();
-                }
+            if c == self.open || c == self.close {
+                return &self.input[start..pos];
             }
+            self.cur.next();
         }
         &self.input[start..self.input.len()]
     }
@@ -274,6 +308,21 @@ impl<'a> Parser<'a> {
     /// the format string
     fn argument(&mut self) -> Argument<'a> {
         let pos = self.position();
+        // A conditional section has a prefix in place of a format spec,
+        // running up to the closing delimiter.
+        let prefix = if self.consume('?') {
+            let start = self.cur.peek().map_or(self.input.len(), |&(pos, _)| pos);
+            let end = loop {
+                match self.cur.peek() {
+                    Some(&(pos, c)) if c == self.open || c == self.close => break pos,
+                    Some(_) => { self.cur.next(); }
+                    None => break self.input.len(),
+                }
+            };
+            Some(&self.input[start..end])
+        } else {
+            None
+        };
         let format = self.format();
 
         // Resolve position after parsing format spec.
@@ -289,6 +338,7 @@ impl<'a> Parser<'a> {
         Argument {
             position: pos,
             format: format,
+            prefix: prefix,
         }
     }
 
@@ -301,7 +351,7 @@ impl<'a> Parser<'a> {
             Some(ArgumentIs(i))
         } else {
             match self.cur.peek() {
-                Some(&(_, c)) if c.is_alphabetic() => Some(ArgumentNamed(self.word())),
+                Some(&(_, c)) if c.is_alphabetic() => Some(ArgumentNamed(self.path())),
 
                 // This is an `ArgumentNext`.
                 // Record the fact and do the resolution after parsing the
@@ -386,7 +436,20 @@ impl<'a> Parser<'a> {
         if self.consume('?') {
             spec.ty = "?";
         } else {
+            let start = self.cur.peek().map(|&(pos, _)| pos);
             spec.ty = self.word();
+            // A format type may be followed by an argument in parentheses,
+            // running up to the first `)`, which becomes part of the type.
+            if let (Some(start), false) = (start, spec.ty.is_empty()) {
+                if self.consume('(') {
+                    while let Some((pos, c)) = self.cur.next() {
+                        if c == ')' {
+                            spec.ty = &self.input[start..pos + 1];
+                            break;
+                        }
+                    }
+                }
+            }
         }
         spec
     }
@@ -403,7 +466,7 @@ impl<'a> Parser<'a> {
             }
         } else {
             let tmp = self.cur.clone();
-            let word = self.word();
+            let word = self.path();
             if word.is_empty() {
                 self.cur = tmp;
                 CountImplied
@@ -441,6 +504,28 @@ impl<'a> Parser<'a> {
         &self.input[start..self.input.len()]
     }
 
+    /// Parses a path starting at the current position: words separated by
+    /// `.`, as in `user.name`, naming a field within an argument. A `.` which
+    /// is not followed by another word is not part of the path.
+    fn path(&mut self) -> &'a str {
+        let start = match self.cur.peek() {
+            Some(&(pos, _)) => pos,
+            None => return &self.input[..0],
+        };
+        let mut end = start + self.word().len();
+        while end != start {
+            let mut next = self.cur.clone();
+            match (next.next(), next.peek()) {
+                (Some((_, '.')), Some(&(pos, c))) if UnicodeXID::is_xid_start(c) => {
+                    self.cur = next;
+                    end = pos + self.word().len();
+                }
+                _ => break,
+            }
+        }
+        &self.input[start..end]
+    }
+
     /// Optionally parses an integer at the current position. This doesn't deal
     /// with overflow at all, it's just accumulating digits.
     fn integer(&mut self) -> Option<usize> {
@@ -499,6 +584,20 @@ mod tests {
         same("\\}}", &[String("\\"), String("}")]);
     }
 
+    fn errspan(s: &str, span: Range<usize>) {
+        let mut p = Parser::new(s);
+        while let Some(_) = p.next() {}
+        assert_eq!(p.errors[0].2, span);
+    }
+
+    #[test]
+    fn error_spans() {
+        errspan("ab}", 2..3);
+        errspan("{0 x}", 3..4);
+        errspan("a {", 3..3);
+        errspan("{:€}", 2..5);
+    }
+
     #[test]
     fn invalid01() {
         musterr("{")
@@ -525,14 +624,32 @@ mod tests {
         same("{}",
              &[NextArgument(Argument {
                    position: ArgumentIs(0),
+                   prefix: None,
+                   format: fmtdflt(),
+               })]);
+    }
+    #[test]
+    fn format_prefix() {
+        same("{tel?Tel: }",
+             &[NextArgument(Argument {
+                   position: ArgumentNamed("tel"),
+                   prefix: Some("Tel: "),
+                   format: fmtdflt(),
+               })]);
+        same("{?}",
+             &[NextArgument(Argument {
+                   position: ArgumentIs(0),
+                   prefix: Some(""),
                    format: fmtdflt(),
                })]);
+        musterr("{a?b{c}");
     }
     #[test]
     fn format_position() {
         same("{3}",
              &[NextArgument(Argument {
                    position: ArgumentIs(3),
+                   prefix: None,
                    format: fmtdflt(),
                })]);
     }
@@ -541,6 +658,7 @@ mod tests {
         same("{3:}",
              &[NextArgument(Argument {
                    position: ArgumentIs(3),
+                   prefix: None,
                    format: fmtdflt(),
                })]);
     }
@@ -549,6 +667,7 @@ mod tests {
         same("{3:a}",
              &[NextArgument(Argument {
                    position: ArgumentIs(3),
+                   prefix: None,
                    format: FormatSpec {
                        fill: None,
                        align: AlignUnknown,
@@ -560,10 +679,27 @@ mod tests {
                })]);
     }
     #[test]
+    fn format_type_argument() {
+        same("{:a(1#b|c)}",
+             &[NextArgument(Argument {
+                   position: ArgumentIs(0),
+                   prefix: None,
+                   format: FormatSpec {
+                       fill: None,
+                       align: AlignUnknown,
+                       flags: 0,
+                       precision: CountImplied,
+                       width: CountImplied,
+                       ty: "a(1#b|c)",
+                   },
+               })]);
+    }
+    #[test]
     fn format_align_fill() {
         same("{3:>}",
              &[NextArgument(Argument {
                    position: ArgumentIs(3),
+                   prefix: None,
                    format: FormatSpec {
                        fill: None,
                        align: AlignRight,
@@ -576,6 +712,7 @@ mod tests {
         same("{3:0<}",
              &[NextArgument(Argument {
                    position: ArgumentIs(3),
+                   prefix: None,
                    format: FormatSpec {
                        fill: Some('0'),
                        align: AlignLeft,
@@ -588,6 +725,7 @@ mod tests {
         same("{3:*<abcd}",
              &[NextArgument(Argument {
                    position: ArgumentIs(3),
+                   prefix: None,
                    format: FormatSpec {
                        fill: Some('*'),
                        align: AlignLeft,
@@ -603,6 +741,7 @@ mod tests {
         same("{:10s}",
              &[NextArgument(Argument {
                    position: ArgumentIs(0),
+                   prefix: None,
                    format: FormatSpec {
                        fill: None,
                        align: AlignUnknown,
@@ -615,6 +754,7 @@ mod tests {
         same("{:10$.10s}",
              &[NextArgument(Argument {
                    position: ArgumentIs(0),
+                   prefix: None,
                    format: FormatSpec {
                        fill: None,
                        align: AlignUnknown,
@@ -627,6 +767,7 @@ mod tests {
         same("{:.*s}",
              &[NextArgument(Argument {
                    position: ArgumentIs(1),
+                   prefix: None,
                    format: FormatSpec {
                        fill: None,
                        align: AlignUnknown,
@@ -639,6 +780,7 @@ mod tests {
         same("{:.10$s}",
              &[NextArgument(Argument {
                    position: ArgumentIs(0),
+                   prefix: None,
                    format: FormatSpec {
                        fill: None,
                        align: AlignUnknown,
@@ -651,6 +793,7 @@ mod tests {
         same("{:a$.b$s}",
              &[NextArgument(Argument {
                    position: ArgumentIs(0),
+                   prefix: None,
                    format: FormatSpec {
                        fill: None,
                        align: AlignUnknown,
@@ -666,6 +809,7 @@ mod tests {
         same("{:-}",
              &[NextArgument(Argument {
                    position: ArgumentIs(0),
+                   prefix: None,
                    format: FormatSpec {
                        fill: None,
                        align: AlignUnknown,
@@ -678,6 +822,7 @@ mod tests {
         same("{:+#}",
              &[NextArgument(Argument {
                    position: ArgumentIs(0),
+                   prefix: None,
                    format: FormatSpec {
                        fill: None,
                        align: AlignUnknown,
@@ -694,6 +839,7 @@ mod tests {
              &[String("abcd "),
                NextArgument(Argument {
                    position: ArgumentIs(3),
+                   prefix: None,
                    format: FormatSpec {
                        fill: None,
                        align: AlignUnknown,
@@ -0,0 +1,156 @@
+//! Formatting with each argument passed through a callback before it is
+//! written, so its text can be inspected or replaced, or its failure to
+//! format can be worked around.
+use alloc::borrow::Cow;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use core::fmt;
+use erase;
+use section;
+use {Arguments, ArgumentV1, FormatArgs, FormatBuf, PreparedFormat};
+use {placeholder, with_small};
+
+// Stands in for an argument, rendering it and handing the result to the
+// interceptor before writing whatever the interceptor returns in its place.
+struct Intercepted<'a, 'i, I: 'i> {
+    index: usize,
+    arg: ArgumentV1<'a>,
+    intercept: &'i RefCell<I>,
+}
+
+impl<'a, 'i, I> fmt::Display for Intercepted<'a, 'i, I>
+    where I: FnMut(usize, Result<String, fmt::Error>) -> Result<String, fmt::Error>
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text = erase::render(self.arg, erase::options(f));
+        let text = (&mut *self.intercept.borrow_mut())(self.index, text)?;
+        f.write_str(&text)
+    }
+}
+
+// Call `f` with `args` routed through the interceptor, leaving counts as
+// they are since they are read rather than formatted.
+fn intercepted<'a, I, F, R>(args: &[ArgumentV1<'a>], slots: &[(usize, Option<Cow<str>>)],
+                            intercept: I, f: F) -> R
+    where I: FnMut(usize, Result<String, fmt::Error>) -> Result<String, fmt::Error>,
+          F: FnOnce(&[ArgumentV1]) -> R
+{
+    let intercept = RefCell::new(intercept);
+    let wrappers: Vec<_> = args.iter().zip(slots).map(|(&arg, &(idx, ref ty))| {
+        ty.as_ref().map(|_| Intercepted { index: idx, arg: arg, intercept: &intercept })
+    }).collect();
+    let routed: Vec<_> = wrappers.iter().zip(args).map(|(wrapper, &arg)| match *wrapper {
+        Some(ref wrapper) => ArgumentV1::new(wrapper, <Intercepted<I> as fmt::Display>::fmt),
+        None => arg,
+    }).collect();
+    f(&routed)
+}
+
+// Adapt a user's interceptor to see only arguments which formatted.
+fn editing<I>(mut intercept: I) -> impl FnMut(usize, Result<String, fmt::Error>)
+    -> Result<String, fmt::Error>
+    where I: FnMut(usize, &mut String)
+{
+    move |idx, text| text.map(|mut text| {
+        intercept(idx, &mut text);
+        text
+    })
+}
+
+// Adapt a list of failures to be filled in with arguments which did not
+// format, each replaced by a placeholder.
+fn recording<'e>(failures: &'e mut Vec<(usize, fmt::Error)>)
+    -> impl FnMut(usize, Result<String, fmt::Error>) -> Result<String, fmt::Error> + 'e
+{
+    move |idx, text| Ok(text.unwrap_or_else(|e| {
+        failures.push((idx, e));
+        String::from("<format error>")
+    }))
+}
+
+impl<'s, T: FormatArgs> PreparedFormat<'s, T> {
+    /// Call a function accepting `Arguments` with the contents of this buffer,
+    /// passing each argument through `intercept` as it is formatted.
+    ///
+    /// The interceptor is given the index of the argument and its text, with
+    /// any padding already applied, and may change or replace that text
+    /// before it is written. Arguments used only as counts are not passed.
+    pub fn with_interceptor<I, F, R>(&self, t: &T, intercept: I, f: F) -> R
+        where I: FnMut(usize, &mut String), F: FnOnce(Arguments) -> R
+    {
+        self.routed(t, editing(intercept), f)
+    }
+
+    /// Format the given value to a `String`, writing `<format error>` in
+    /// place of any argument which fails to format rather than giving up.
+    ///
+    /// The index of each argument which failed is returned along with its
+    /// error, in the order they were written.
+    pub fn format_lossy(&self, t: &T) -> (String, Vec<(usize, fmt::Error)>) {
+        let mut failures = Vec::new();
+        let text = self.routed(t, recording(&mut failures), ::alloc::fmt::format);
+        (text, failures)
+    }
+
+    fn routed<I, F, R>(&self, t: &T, intercept: I, f: F) -> R
+        where I: FnMut(usize, Result<String, fmt::Error>) -> Result<String, fmt::Error>,
+              F: FnOnce(Arguments) -> R
+    {
+        with_small(self.inner.args.len(), placeholder(), |i| self.argument(t, i), |args| {
+            self.clamp_counts(t, args);
+            let inner = &self.inner;
+            section::resolved(args, &inner.slots, |args| {
+                intercepted(args, &inner.slots, intercept, |args| inner.with_arguments(args, f))
+            })
+        })
+    }
+}
+
+impl<'s> FormatBuf<'s> {
+    /// Call a function accepting `Arguments` with the contents of this buffer,
+    /// passing each argument through `intercept` as it is formatted.
+    ///
+    /// The interceptor is given the index of the argument and its text, with
+    /// any padding already applied, and may change or replace that text
+    /// before it is written:
+    ///
+    /// ```rust
+    /// use runtime_fmt::{FormatBuf, Param};
+    ///
+    /// let params = [Param::normal(&"alice"), Param::normal(&"hunter2")];
+    /// let buf = FormatBuf::new("{} logged in with [{:>8}]", &params).unwrap();
+    /// let logged = buf.with_interceptor(|idx, text| if idx == 1 {
+    ///     *text = "***".into();
+    /// }, std::fmt::format);
+    /// assert_eq!(logged, "alice logged in with [***]");
+    /// ```
+    ///
+    /// Arguments used only as counts are not passed.
+    pub fn with_interceptor<I, F, R>(&self, intercept: I, f: F) -> R
+        where I: FnMut(usize, &mut String), F: FnOnce(Arguments) -> R
+    {
+        self.routed(editing(intercept), f)
+    }
+
+    /// Format this buffer to a `String`, writing `<format error>` in place of
+    /// any argument which fails to format rather than giving up.
+    ///
+    /// The index of each argument which failed is returned along with its
+    /// error, in the order they were written.
+    pub fn format_lossy(&self) -> (String, Vec<(usize, fmt::Error)>) {
+        let mut failures = Vec::new();
+        let text = self.routed(recording(&mut failures), ::alloc::fmt::format);
+        (text, failures)
+    }
+
+    fn routed<I, F, R>(&self, intercept: I, f: F) -> R
+        where I: FnMut(usize, Result<String, fmt::Error>) -> Result<String, fmt::Error>,
+              F: FnOnce(Arguments) -> R
+    {
+        let inner = &self.inner;
+        section::resolved(&inner.args, &inner.slots, |args| {
+            intercepted(args, &inner.slots, intercept, |args| inner.with_arguments(args, f))
+        })
+    }
+}
@@ -0,0 +1,86 @@
+//! A pool of strings shared between prepared formats.
+//!
+//! Formats which have been made independent of their format strings with
+//! `into_owned` each keep their own copy of their text. When many formats
+//! share text, such as a common prefix or the same argument names, preparing
+//! them with `PreparedFormat::prepare_interned` instead stores each distinct
+//! string once in an `Interner`, which the formats then borrow from.
+
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::sync::Mutex;
+
+use {Error, FormatArgs, Parsed, PreparedFormat};
+
+/// A set of strings which may be borrowed for as long as the set exists.
+///
+/// Strings are only ever added, so a string once interned stays at the same
+/// address until the interner is dropped.
+#[derive(Debug, Default)]
+pub struct Interner {
+    strings: Mutex<HashSet<Box<str>>>,
+}
+
+impl Interner {
+    /// Create an empty interner.
+    pub fn new() -> Interner {
+        Interner::default()
+    }
+
+    /// Return a copy of the given string owned by this interner, which is
+    /// the same copy each time the same text is given.
+    pub fn intern(&self, text: &str) -> &str {
+        let mut strings = self.strings.lock().unwrap_or_else(|e| e.into_inner());
+        let ptr: *const str = match strings.get(text) {
+            Some(existing) => &**existing,
+            None => {
+                let boxed = Box::<str>::from(text);
+                let ptr: *const str = &*boxed;
+                strings.insert(boxed);
+                ptr
+            }
+        };
+        // The box is never removed or changed while `self` is borrowed, and
+        // its contents do not move when the set is resized.
+        unsafe { &*ptr }
+    }
+
+    /// Return the number of distinct strings interned so far.
+    pub fn len(&self) -> usize {
+        self.strings.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+
+    /// Return whether no strings have been interned yet.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<'s, T: FormatArgs> PreparedFormat<'s, T> {
+    /// Prepare a format string against a formattable type, storing its text
+    /// in the given interner rather than borrowing it from the format string.
+    ///
+    /// The literal text, argument names and format types of formats prepared
+    /// with the same interner share storage wherever they are the same.
+    pub fn prepare_interned<'e>(spec: &'e str, pool: &'s Interner) -> Result<Self, Error<'e>> {
+        let prepared = PreparedFormat::<T>::prepare(spec)?;
+        let Parsed { spec, pieces, args, slots, fmt, names, indices, .. } = prepared.inner;
+        let intern = |text: Cow<str>| pool.intern(&text);
+        let borrowed: Vec<&'s str> = pieces.into_iter().map(&intern).collect();
+        Ok(PreparedFormat {
+            inner: Parsed {
+                spec: Cow::Borrowed(intern(spec)),
+                pieces: borrowed.iter().map(|&p| Cow::Borrowed(p)).collect(),
+                borrowed: Some(borrowed),
+                args: args,
+                slots: slots.into_iter()
+                    .map(|(idx, ty)| (idx, ty.map(|ty| Cow::Borrowed(intern(ty)))))
+                    .collect(),
+                fmt: fmt,
+                names: names.into_iter().map(|n| Cow::Borrowed(intern(n))).collect(),
+                indices: indices,
+            },
+            count_limits: prepared.count_limits,
+        })
+    }
+}
@@ -14,87 +14,408 @@
 //! that used by `std::fmt`, including support for positional and named
 //! arguments. This crate shells out to the standard library implementations
 //! for as much as possible to ensure feature parity.
+//!
+//! Variables in scope are not captured by name, since the names in the format
+//! string are not known until runtime, but may be listed after a semicolon,
+//! as in `rt_format!(spec, 1; name, count)`.
+//!
+//! Format strings may also be checked once ahead of time against a type
+//! implementing `FormatArgs` by using `PreparedFormat`. The companion
+//! `runtime-fmt-derive` crate provides `#[derive(FormatArgs)]`, which
+//! exposes each field of a struct by name and by position in declaration
+//! order. As an extension of the `std::fmt` syntax, a field whose type also
+//! derives `FormatArgs` may be looked into with a dotted name, as in
+//! `{user.name}`.
+//!
+//! A few format types beyond those of `std::fmt` are also understood, such
+//! as `{:grp}` for digit grouping and `{:hex}` and `{:b64}` for byte slices;
+//! see the `custom` module.
+//!
+//! A choice such as `{n:plural(1#one item|other#items)}` selects text by an
+//! integer argument. A conditional section such as `{phone?Tel: }` writes
+//! `Tel: ` and then the argument, or nothing at all if the argument's
+//! `Display` text is empty or only whitespace. Both are decided each time
+//! they are formatted, so they work with `PreparedFormat` as well.
+//!
+//! The default `std` feature enables printing and writing to `io::Write`.
+//! Without it, the crate depends only on `core` and `alloc`, and output goes
+//! through `fmt::Write`.
+//!
+//! The `async` feature adds `write_async`, for output to a
+//! `futures::io::AsyncWrite`. The `serde` feature allows the `FormatShape` of a
+//! prepared format to be serialized, so it can be bound again without parsing.
+//! The `json` feature adds `spec::to_json`, describing a format string for tools
+//! outside of Rust.
+#![cfg_attr(not(feature = "std"), no_std)]
 #![feature(fmt_internals)]
-#![feature(unicode_internals)]
 #![feature(specialization)]
-#![feature(print_internals)]
-#![feature(rustc_private)]
+
+#[cfg(feature = "std")]
+extern crate core;
+#[cfg(feature = "std")]
+extern crate alloc;
+#[cfg(not(feature = "std"))]
+#[macro_use]
+extern crate alloc;
+#[cfg(feature = "async")]
+extern crate futures_io;
+#[cfg(feature = "serde")]
+extern crate serde;
 
 #[doc(hidden)]
 #[inline]
+#[cfg(feature = "std")]
 pub fn _print(args: Arguments) {
-    std::io::_print(args)
+    print!("{}", args)
 }
 
+#[cfg(feature = "async")]
+mod async_write;
+#[cfg(feature = "std")]
+pub mod cache;
+#[cfg(feature = "std")]
+pub mod intern;
+mod choice;
 pub mod codegen;
+pub mod custom;
 mod erase;
+mod intercept;
 mod macros;
+pub mod map;
+mod section;
+pub mod shape;
+pub mod spec;
 
 // fmt_macros.rs is from rust/src/libfmt_macros/lib.rs
 // copy-pasted rather than externed to avoid dynamically linking libstd
 mod fmt_macros;
 
+#[cfg(feature = "std")]
 use std::io;
-use std::fmt::{self, Arguments, ArgumentV1};
-use std::fmt::rt::v1;
-use std::borrow::Cow;
-use std::marker::PhantomData;
+use core::fmt::{self, Arguments, ArgumentV1};
+use core::borrow::Borrow;
+use core::convert::TryFrom;
+use core::fmt::rt::v1;
+use core::marker::PhantomData;
+use core::ops::{Deref, Range};
+use alloc::borrow::Cow;
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
 
 pub use codegen::FormatArgs;
+pub use spec::{parse_spec, validate_braces};
 
 /// An error during parsing or formatting.
+///
+/// Errors may be cloned, but an `io::Error` cannot be cloned faithfully: the
+/// clone of an `Io` error keeps its OS error code, or otherwise its kind and
+/// message, but not any error it wraps.
 #[derive(Debug)]
 pub enum Error<'a> {
     /// Invalid format string syntax.
-    BadSyntax(Vec<(String, Option<String>)>),
-    /// A format specifier referred to an out-of-range index.
+    ///
+    /// Each entry holds a message, an optional note, and the byte range of
+    /// the format string to which the error applies.
+    BadSyntax(Vec<(String, Option<String>, Range<usize>)>),
+    /// A format specifier referred to an index which its `FormatArgs` type
+    /// does not have, or a `FormatShape` referred to a missing position or
+    /// held a number of pieces its arguments could not have left.
     BadIndex(usize),
+    /// A format specifier referred to an index beyond the parameters given,
+    /// of which there were `supplied`.
+    MissingArgument {
+        index: usize,
+        supplied: usize,
+    },
     /// A format specifier referred to a non-existent name.
-    BadName(&'a str),
+    BadName(Cow<'a, str>),
+    /// A format specifier's name, compared without regard to case, matched
+    /// parameters of more than one name.
+    AmbiguousName(Cow<'a, str>),
+    /// More than one parameter given to a strictly-checked format string had
+    /// the same name.
+    DuplicateName(Cow<'a, str>),
     /// A format specifier referred to a non-existent type.
-    NoSuchFormat(&'a str),
+    NoSuchFormat(Cow<'a, str>),
     /// A format specifier's type was not satisfied by its argument.
     UnsatisfiedFormat {
         idx: usize,
         must_implement: &'static str,
     },
-    /// A parameter was of a type not suitable for use as a count.
-    BadCount(usize),
+    /// A parameter was not suitable for use as a count, for the given reason.
+    ///
+    /// The type of the parameter is given where it is known, as it is for a
+    /// `Param` but not for the fields of a `FormatArgs` type.
+    BadCount {
+        idx: usize,
+        ty: Option<&'static str>,
+        reason: CountError,
+    },
+    /// A named parameter was not referred to by a strictly-checked format
+    /// string.
+    UnusedArgument {
+        name: Cow<'a, str>,
+    },
+    /// A nameless parameter was not referred to by an exhaustively-checked
+    /// format string.
+    UnusedIndex(usize),
+    /// A width or precision exceeded the `Limits` it was parsed with.
+    CountTooLarge {
+        value: usize,
+        limit: usize,
+    },
     /// An I/O error from an `rt_write!` or `rt_writeln!` call.
-    Io(std::io::Error),
+    #[cfg(feature = "std")]
+    Io(io::Error),
     /// A formatting error from an `rt_write!` or `rt_writeln!` call.
-    Fmt(std::fmt::Error),
+    Fmt(fmt::Error),
+    /// A formatting error from an `rt_write!` or `rt_writeln!` call, raised
+    /// by the formatting trait implementation of the argument at `idx`
+    /// rather than by the destination.
+    FmtAt {
+        idx: usize,
+        source: fmt::Error,
+    },
+    /// The default given to `FormatBuf::new_with_default` could not stand in
+    /// for the unmatched `name`, failing as `source` describes with the
+    /// default as argument 0.
+    Defaulted {
+        name: Cow<'a, str>,
+        source: Box<OwnedError>,
+    },
+}
+
+/// Why a parameter could not be used as a width or precision.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CountError {
+    /// The parameter is not an integer.
+    WrongType,
+    /// The parameter is a negative integer.
+    Negative,
+    /// The parameter is an integer too large for a `usize`.
+    Overflow,
+}
+
+/// The error within the `io::Error` returned when `write_io_exact` finds too
+/// little space remaining, so that it can be told apart from a short write.
+///
+/// It is of kind `Other`, and may be found with `get_ref` and `downcast_ref`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct DoesNotFit {
+    /// The length of the formatted output, in bytes.
+    pub len: usize,
+    /// The space which was remaining, in bytes.
+    pub remaining: usize,
+}
+
+impl fmt::Display for DoesNotFit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "formatted output of {} bytes does not fit in the {} remaining",
+               self.len, self.remaining)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for DoesNotFit {
+    fn description(&self) -> &str {
+        "formatted output does not fit"
+    }
+}
+
+/// An `Error` which does not borrow from the format string.
+pub type OwnedError = Error<'static>;
+
+impl<'a> Error<'a> {
+    /// Convert this error into one which owns all of its data, so that it may
+    /// outlive the format string it was produced from.
+    pub fn into_owned(self) -> OwnedError {
+        match self {
+            Error::BadSyntax(errors) => Error::BadSyntax(errors),
+            Error::BadIndex(i) => Error::BadIndex(i),
+            Error::MissingArgument { index, supplied } =>
+                Error::MissingArgument { index: index, supplied: supplied },
+            Error::BadName(n) => Error::BadName(n.into_owned().into()),
+            Error::AmbiguousName(n) => Error::AmbiguousName(n.into_owned().into()),
+            Error::DuplicateName(n) => Error::DuplicateName(n.into_owned().into()),
+            Error::NoSuchFormat(c) => Error::NoSuchFormat(c.into_owned().into()),
+            Error::UnsatisfiedFormat { idx, must_implement } =>
+                Error::UnsatisfiedFormat { idx: idx, must_implement: must_implement },
+            Error::BadCount { idx, ty, reason } =>
+                Error::BadCount { idx: idx, ty: ty, reason: reason },
+            Error::UnusedArgument { name } =>
+                Error::UnusedArgument { name: name.into_owned().into() },
+            Error::UnusedIndex(i) => Error::UnusedIndex(i),
+            Error::CountTooLarge { value, limit } =>
+                Error::CountTooLarge { value: value, limit: limit },
+            #[cfg(feature = "std")]
+            Error::Io(e) => Error::Io(e),
+            Error::Fmt(e) => Error::Fmt(e),
+            Error::FmtAt { idx, source } => Error::FmtAt { idx: idx, source: source },
+            Error::Defaulted { name, source } => Error::Defaulted {
+                name: name.into_owned().into(),
+                source: source,
+            },
+        }
+    }
+
+    // The same error, with the default at argument 0, if it is about an
+    // argument which `new_with_default` gave the default to.
+    fn defaulted(self, names: &[Cow<'a, str>]) -> Self {
+        let idx = match self {
+            Error::UnsatisfiedFormat { idx, .. } |
+            Error::BadCount { idx, .. } |
+            Error::FmtAt { idx, .. } => idx,
+            _ => return self,
+        };
+        match defaulted_name(names, idx) {
+            Some(name) => Error::Defaulted {
+                name: name.clone(),
+                source: Box::new(self.reindexed(0).into_owned()),
+            },
+            None => self,
+        }
+    }
+
+    fn reindexed(self, idx: usize) -> Self {
+        match self {
+            Error::UnsatisfiedFormat { must_implement, .. } =>
+                Error::UnsatisfiedFormat { idx: idx, must_implement: must_implement },
+            Error::BadCount { ty, reason, .. } =>
+                Error::BadCount { idx: idx, ty: ty, reason: reason },
+            Error::FmtAt { source, .. } => Error::FmtAt { idx: idx, source: source },
+            e => e,
+        }
+    }
+
+    /// For a `NoSuchFormat`, return the built-in format types which are a
+    /// single edit away from the one given, as likely typos.
+    ///
+    /// Specifiers of one character are given no suggestions, since every
+    /// other one-character type would qualify.
+    pub fn suggestions(&self) -> Vec<&'static str> {
+        match *self {
+            Error::NoSuchFormat(ref c) if c.chars().count() > 1 => erase::FORMAT_TYPES.iter()
+                .cloned()
+                .filter(|name| !name.is_empty() && within_one_edit(c, name))
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+// Whether `a` becomes `b` by inserting, deleting, or substituting one
+// character, or by swapping two adjacent ones.
+fn within_one_edit(a: &str, b: &str) -> bool {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let prefix = a.iter().zip(&b).take_while(|&(x, y)| x == y).count();
+    let (a, b) = (&a[prefix..], &b[prefix..]);
+    match (a.len(), b.len()) {
+        (0, 0) => true,
+        (x, y) if x == y + 1 => a[1..] == *b,
+        (x, y) if x + 1 == y => *a == b[1..],
+        (x, y) if x == y => {
+            a[1..] == b[1..] || (x >= 2 && a[0] == b[1] && a[1] == b[0] && a[2..] == b[2..])
+        }
+        _ => false,
+    }
 }
 
-impl<'a> From<std::io::Error> for Error<'a> {
-    fn from(e: std::io::Error) -> Self {
+#[cfg(feature = "std")]
+impl<'a> From<io::Error> for Error<'a> {
+    fn from(e: io::Error) -> Self {
         Error::Io(e)
     }
 }
 
-impl<'a> From<std::fmt::Error> for Error<'a> {
-    fn from(e: std::fmt::Error) -> Self {
+impl<'a> From<fmt::Error> for Error<'a> {
+    fn from(e: fmt::Error) -> Self {
         Error::Fmt(e)
     }
 }
 
+impl<'a> Clone for Error<'a> {
+    fn clone(&self) -> Self {
+        match *self {
+            Error::BadSyntax(ref errors) => Error::BadSyntax(errors.clone()),
+            Error::BadIndex(i) => Error::BadIndex(i),
+            Error::MissingArgument { index, supplied } =>
+                Error::MissingArgument { index: index, supplied: supplied },
+            Error::BadName(ref n) => Error::BadName(n.clone()),
+            Error::AmbiguousName(ref n) => Error::AmbiguousName(n.clone()),
+            Error::DuplicateName(ref n) => Error::DuplicateName(n.clone()),
+            Error::NoSuchFormat(ref c) => Error::NoSuchFormat(c.clone()),
+            Error::UnsatisfiedFormat { idx, must_implement } =>
+                Error::UnsatisfiedFormat { idx: idx, must_implement: must_implement },
+            Error::BadCount { idx, ty, reason } =>
+                Error::BadCount { idx: idx, ty: ty, reason: reason },
+            Error::UnusedArgument { ref name } => Error::UnusedArgument { name: name.clone() },
+            Error::UnusedIndex(i) => Error::UnusedIndex(i),
+            Error::CountTooLarge { value, limit } =>
+                Error::CountTooLarge { value: value, limit: limit },
+            #[cfg(feature = "std")]
+            Error::Io(ref e) => Error::Io(match e.raw_os_error() {
+                Some(code) => io::Error::from_raw_os_error(code),
+                None => io::Error::new(e.kind(), e.to_string()),
+            }),
+            Error::Fmt(e) => Error::Fmt(e),
+            Error::FmtAt { idx, source } => Error::FmtAt { idx: idx, source: source },
+            Error::Defaulted { ref name, ref source } =>
+                Error::Defaulted { name: name.clone(), source: source.clone() },
+        }
+    }
+}
+
+/// I/O errors are passed through unchanged, and problems with the format
+/// string become `InvalidInput` errors carrying its message.
+#[cfg(feature = "std")]
+impl<'a> From<Error<'a>> for io::Error {
+    fn from(e: Error<'a>) -> Self {
+        match e {
+            Error::Io(e) => e,
+            Error::Fmt(e) | Error::FmtAt { source: e, .. } => fmt_error(e),
+            e => io::Error::new(io::ErrorKind::InvalidInput, e.to_string()),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
 impl<'a> std::error::Error for Error<'a> {
     fn description(&self) -> &str {
         match *self {
             Error::BadSyntax(_) => "bad syntax",
             Error::BadIndex(_) => "out-of-range index",
+            Error::MissingArgument{..} => "missing argument",
             Error::BadName(_) => "unknown name",
+            Error::AmbiguousName(_) => "ambiguous name",
+            Error::DuplicateName(_) => "duplicate name",
             Error::NoSuchFormat(_) => "bad formatting specifier",
             Error::UnsatisfiedFormat{..} => "formatting trait not satisfied",
-            Error::BadCount(_) => "non-integer used as count",
+            Error::BadCount{..} => "bad value used as count",
+            Error::UnusedArgument{..} | Error::UnusedIndex(_) => "unused argument",
+            Error::CountTooLarge{..} => "count too large",
             Error::Io(ref e) => e.description(),
             Error::Fmt(ref f) => f.description(),
+            Error::FmtAt{..} => "formatting trait failed",
+            Error::Defaulted{..} => "default not usable",
         }
     }
     fn cause(&self) -> Option<&dyn std::error::Error> {
         match *self {
             Error::Io(ref e) => Some(e),
             Error::Fmt(ref e) => Some(e),
+            Error::FmtAt { ref source, .. } => Some(source),
+            Error::Defaulted { ref source, .. } => Some(&**source),
+            _ => None,
+        }
+    }
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match *self {
+            Error::Io(ref e) => Some(e),
+            Error::Fmt(ref e) => Some(e),
+            Error::FmtAt { ref source, .. } => Some(source),
+            Error::Defaulted { ref source, .. } => Some(&**source),
             _ => None,
         }
     }
@@ -104,13 +425,41 @@ impl<'a> fmt::Display for Error<'a> {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             Error::BadIndex(i) => write!(fmt, "index {} out of range", i),
-            Error::BadName(n) => write!(fmt, "unknown name {:?}", n),
-            Error::NoSuchFormat(c) => write!(fmt, "bad formatting specifier {:?}", c),
+            Error::MissingArgument { index, supplied } =>
+                write!(fmt, "argument {} is missing; {} were supplied", index, supplied),
+            Error::BadName(ref n) => write!(fmt, "unknown name {:?}", n),
+            Error::AmbiguousName(ref n) => write!(fmt, "ambiguous name {:?}", n),
+            Error::DuplicateName(ref n) => write!(fmt, "more than one argument is named {:?}", n),
+            Error::NoSuchFormat(ref c) => {
+                write!(fmt, "bad formatting specifier {:?}; known specifiers are", c)?;
+                for (i, name) in erase::FORMAT_TYPES.iter().enumerate() {
+                    write!(fmt, "{} {:?}", if i > 0 { "," } else { "" }, name)?;
+                }
+                Ok(())
+            }
             Error::UnsatisfiedFormat { idx, must_implement } =>
                 write!(fmt, "argument {} does not implement {}", idx, must_implement),
-            Error::BadCount(i) => write!(fmt, "argument {} cannot be used as a count", i),
+            Error::BadCount { idx, ty, reason } => {
+                write!(fmt, "argument {}", idx)?;
+                if let Some(ty) = ty {
+                    write!(fmt, " of type `{}`", ty)?;
+                }
+                fmt.write_str(match reason {
+                    CountError::WrongType => " cannot be used as a count",
+                    CountError::Negative => " is negative and cannot be used as a count",
+                    CountError::Overflow => " is too large to be used as a count",
+                })
+            }
+            Error::UnusedArgument { ref name } => write!(fmt, "argument {:?} is never used", name),
+            Error::UnusedIndex(i) => write!(fmt, "argument {} is never used", i),
+            Error::CountTooLarge { value, limit } =>
+                write!(fmt, "count {} exceeds the limit of {}", value, limit),
+            #[cfg(feature = "std")]
             Error::Io(ref e) => e.fmt(fmt),
             Error::Fmt(ref e) => e.fmt(fmt),
+            Error::FmtAt { idx, ref source } => write!(fmt, "{} in argument {}", source, idx),
+            Error::Defaulted { ref name, ref source } =>
+                write!(fmt, "the default for {:?} failed: {}", name, source),
             Error::BadSyntax(ref errors) => {
                 for (i, err) in errors.iter().enumerate() {
                     if i > 0 {
@@ -128,10 +477,18 @@ impl<'a> fmt::Display for Error<'a> {
 }
 
 /// A type-erased parameter, with an optional name.
+///
+/// Any integer value which fits in a `usize`, or a `NonZeroUsize`, may be
+/// used as a width or precision.
 pub struct Param<'a> {
-    name: Option<&'static str>,
-    value: &'a dyn erase::Format,
-    as_usize: Option<usize>,
+    name: Option<&'a str>,
+    value: Value<'a>,
+    as_usize: Result<usize, CountError>,
+}
+
+enum Value<'a> {
+    Borrowed(&'a dyn erase::Format),
+    Owned(Box<dyn erase::Format>),
 }
 
 impl<'a> Param<'a> {
@@ -141,34 +498,318 @@ impl<'a> Param<'a> {
         Param {
             name: None,
             as_usize: t.as_usize(),
-            value: t,
+            value: Value::Borrowed(t),
+        }
+    }
+
+    /// Create a nameless parameter which takes ownership of the given value.
+    ///
+    /// This allows parameters to be built inline from temporaries, such as
+    /// values computed in a loop, at the cost of an allocation.
+    pub fn owned<T: 'static>(t: T) -> Param<'a> {
+        use erase::Format;
+        Param {
+            name: None,
+            as_usize: t.as_usize(),
+            value: Value::Owned(Box::new(t)),
         }
     }
 
     /// Create a named parameter from the given value.
-    pub fn named<T>(name: &'static str, t: &'a T) -> Param<'a> {
+    pub fn named<T>(name: &'a str, t: &'a T) -> Param<'a> {
         use erase::Format;
         Param {
             name: Some(name),
             as_usize: t.as_usize(),
-            value: t,
+            value: Value::Borrowed(t),
+        }
+    }
+
+    /// Create a named parameter for each entry of a map, or any other
+    /// sequence of key-value pairs.
+    ///
+    /// This suits parameters whose names are not known until runtime, such
+    /// as those loaded from a configuration file. To format many maps with
+    /// the same format string, prepare it once with `map::MapFormat` instead.
+    ///
+    /// The parameters are also numbered in the order the entries are given,
+    /// so that `{0}` refers to the first. For a `BTreeMap` that is the order
+    /// of its keys, making positions as reliable as names; for a `HashMap`
+    /// it is arbitrary, and positions should not be used.
+    ///
+    /// ```rust
+    /// use std::collections::BTreeMap;
+    /// use runtime_fmt::{FormatBuf, Param};
+    ///
+    /// let mut scores = BTreeMap::new();
+    /// scores.insert("bob", 7);
+    /// scores.insert("alice", 9);
+    /// let params = Param::from_map(&scores);
+    /// let buf = FormatBuf::new("{0} {1} {bob}", &params).unwrap();
+    /// assert_eq!(buf.format(), "9 7 7");
+    /// ```
+    pub fn from_map<I, K, V>(map: I) -> Vec<Param<'a>>
+        where I: IntoIterator<Item=(&'a K, &'a V)>, K: AsRef<str> + 'a, V: 'a
+    {
+        map.into_iter().map(|(k, v)| Param::named(k.as_ref(), v)).collect()
+    }
+
+    /// Create a nameless parameter for each element of a slice, numbered in
+    /// order, so that `{0}` refers to the first.
+    ///
+    /// A slice cannot implement `FormatArgs`, which checks indices without a
+    /// value at hand and so cannot see its length; this is the way to format
+    /// one whose length is only known at runtime.
+    ///
+    /// ```rust
+    /// use runtime_fmt::{FormatBuf, Param};
+    ///
+    /// let values = vec![3, 1, 4];
+    /// let params = Param::from_slice(&values);
+    /// let buf = FormatBuf::new("{2}{1}{0}", &params).unwrap();
+    /// assert_eq!(buf.format(), "413");
+    /// ```
+    pub fn from_slice<T>(slice: &'a [T]) -> Vec<Param<'a>> {
+        slice.iter().map(Param::normal).collect()
+    }
+
+    /// Return whether this parameter may be used as a width or precision.
+    #[inline]
+    pub fn is_count(&self) -> bool {
+        self.as_usize.is_ok()
+    }
+
+    /// Return the value of this parameter as a width or precision, if it may
+    /// be used as one.
+    #[inline]
+    pub fn as_count(&self) -> Option<usize> {
+        self.as_usize.ok()
+    }
+
+    #[inline]
+    fn value(&self) -> &dyn erase::Format {
+        match self.value {
+            Value::Borrowed(value) => value,
+            Value::Owned(ref value) => &**value,
+        }
+    }
+}
+
+/// A growable list of parameters, for when their number is not known
+/// until runtime.
+///
+/// Dereferences to `[Param]`, so it may be passed to `FormatBuf::new`.
+#[derive(Default)]
+pub struct Params<'a> {
+    params: Vec<Param<'a>>,
+}
+
+impl<'a> Params<'a> {
+    /// Create an empty list of parameters.
+    #[inline]
+    pub fn new() -> Params<'a> {
+        Params { params: Vec::new() }
+    }
+
+    /// Append a nameless parameter.
+    #[inline]
+    pub fn add<T>(&mut self, t: &'a T) -> &mut Self {
+        self.push(Param::normal(t))
+    }
+
+    /// Append a named parameter.
+    #[inline]
+    pub fn add_named<T>(&mut self, name: &'a str, t: &'a T) -> &mut Self {
+        self.push(Param::named(name, t))
+    }
+
+    /// Append an already-constructed parameter, such as from `Param::owned`.
+    #[inline]
+    pub fn push(&mut self, param: Param<'a>) -> &mut Self {
+        self.params.push(param);
+        self
+    }
+
+    /// Return the parameters as a slice.
+    #[inline]
+    pub fn as_slice(&self) -> &[Param<'a>] {
+        &self.params
+    }
+
+    /// Build only the parameters a format string refers to, calling `get`
+    /// with the index of each.
+    ///
+    /// There are as many parameters as entries in `names`, each taking its
+    /// name from there, or nameless where the entry is empty. Those which
+    /// go unused are left as placeholders, so the result is only suitable
+    /// for formatting the same format string. Only names and indices are
+    /// checked here; `FormatBuf::new` checks the rest.
+    pub fn lazy<'s, F>(spec: &'s str, names: &[&'a str], mut get: F)
+        -> Result<Params<'a>, Error<'s>>
+        where F: FnMut(usize) -> Param<'a>
+    {
+        let mut target = LazyParse { names: names, used: names.iter().map(|_| false).collect() };
+        parse(spec, &mut target, &Limits::default())?;
+        let params = target.used.iter().enumerate().map(|(idx, &used)| {
+            if !used {
+                return Param::normal(&())
+            }
+            let mut param = get(idx);
+            if !names[idx].is_empty() {
+                param.name = Some(names[idx]);
+            }
+            param
+        }).collect();
+        Ok(Params { params: params })
+    }
+}
+
+impl<'a> Deref for Params<'a> {
+    type Target = [Param<'a>];
+
+    #[inline]
+    fn deref(&self) -> &[Param<'a>] {
+        &self.params
+    }
+}
+
+/// Upper bounds on the widths and precisions a format string may request.
+///
+/// Each width pads its argument with that many characters, so a format
+/// string from an untrusted source may otherwise produce arbitrarily large
+/// output. The default imposes no limits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Limits {
+    /// The largest width allowed.
+    pub max_width: usize,
+    /// The largest precision allowed.
+    pub max_precision: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Limits {
+        Limits {
+            max_width: usize::max_value(),
+            max_precision: usize::max_value(),
         }
     }
 }
 
+/// The direction in which text runs, for interpreting alignment.
+///
+/// Running right to left, `<` aligns to the start of the text at the right
+/// and `>` to the end at the left, so the two are swapped in the output.
+/// Centered text, and the default alignment of each format trait, are
+/// unaffected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Direction {
+    /// Left to right, as in English. The default.
+    LeftToRight,
+    /// Right to left, as in Arabic or Hebrew.
+    RightToLeft,
+}
+
+impl Default for Direction {
+    fn default() -> Direction {
+        Direction::LeftToRight
+    }
+}
+
 enum PreparedArgument<T> {
     Normal(fn(&T, &mut fmt::Formatter) -> fmt::Result),
     Usize(fn(&T) -> &usize),
+    // A child reached through a dotted name, at a byte offset within `T`.
+    NestedNormal(usize, fn(&codegen::Opaque, &mut fmt::Formatter) -> fmt::Result),
+    NestedUsize(usize, fn(&codegen::Opaque) -> &usize),
 }
 impl<T> Copy for PreparedArgument<T> {}
 impl<T> Clone for PreparedArgument<T> {
     fn clone(&self) -> Self { *self }
 }
 
+impl<T: FormatArgs> PreparedArgument<T> {
+    fn format<'s>(spec: &'s str, idx: usize) -> Result<Self, Error<'s>> {
+        let (offset, child, index) = match nested::<T>(idx) {
+            Some(found) => found,
+            None => return erase::codegen_get_child::<T>(spec, idx).map(PreparedArgument::Normal),
+        };
+        match (child.get_child)(spec, index) {
+            Ok(func) => Ok(PreparedArgument::NestedNormal(offset, func)),
+            Err(Error::UnsatisfiedFormat { must_implement, .. }) => Err(Error::UnsatisfiedFormat {
+                idx: idx,
+                must_implement: must_implement,
+            }),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn format_usize(idx: usize) -> Result<Self, CountError> {
+        match nested::<T>(idx) {
+            Some((offset, child, index)) => (child.as_usize)(index)
+                .map(|func| PreparedArgument::NestedUsize(offset, func)),
+            None => T::as_usize(idx).map(PreparedArgument::Usize),
+        }.ok_or(CountError::WrongType)
+    }
+
+    #[inline]
+    fn argument<'a>(&self, t: &'a T) -> ArgumentV1<'a> {
+        match *self {
+            PreparedArgument::Normal(func) => ArgumentV1::new(t, func),
+            PreparedArgument::Usize(func) => ArgumentV1::from_usize(func(t)),
+            PreparedArgument::NestedNormal(offset, func) => ArgumentV1::new(field(t, offset), func),
+            PreparedArgument::NestedUsize(offset, func) => {
+                ArgumentV1::from_usize(func(field(t, offset)))
+            }
+        }
+    }
+
+    // The value of a count, or `None` for a value to be formatted.
+    #[inline]
+    fn count<'a>(&self, t: &'a T) -> Option<&'a usize> {
+        match *self {
+            PreparedArgument::Usize(func) => Some(func(t)),
+            PreparedArgument::NestedUsize(offset, func) => Some(func(field(t, offset))),
+            _ => None,
+        }
+    }
+}
+
+// Follow a dotted name down to the value which holds the child it names,
+// returning the byte offset of that value within `T`, its `FormatArgs`, and
+// the index of the child within it.
+fn nested<T: FormatArgs>(idx: usize) -> Option<(usize, codegen::ChildArgs, usize)> {
+    let found = T::get_nested(idx)?;
+    let (mut offset, mut child, mut index) = (found.offset(), found.child(), found.index());
+    while let Some(next) = (child.get_nested)(index) {
+        offset += next.offset();
+        child = next.child();
+        index = next.index();
+    }
+    if (child.validate_index)(index) {
+        Some((offset, child, index))
+    } else {
+        None
+    }
+}
+
+#[inline]
+fn field<T>(t: &T, offset: usize) -> &codegen::Opaque {
+    // `offset` was found by `nested`, and every `Nested` it followed was
+    // made by `codegen::nested`, whose caller vouched for its offset.
+    unsafe { &*((t as *const T as *const u8).add(offset) as *const codegen::Opaque) }
+}
+
 /// A pre-checked format string, ready for values of a specific type to be
 /// formatted against it.
+///
+/// Prepared formats are `Send` and `Sync` regardless of the type they are
+/// prepared against, so one prepared from a `'static` format string may be
+/// shared between threads or stored in a lazily-initialized `static`.
 pub struct PreparedFormat<'s, T: FormatArgs> {
     inner: Parsed<'s, DelayedParse<T>>,
+    // The position of each count argument whose value must be clamped at
+    // formatting time, and the limit to clamp it to.
+    count_limits: Vec<(usize, usize)>,
 }
 
 impl<'s, T: FormatArgs> PreparedFormat<'s, T> {
@@ -178,79 +819,196 @@ impl<'s, T: FormatArgs> PreparedFormat<'s, T> {
     /// will not require checking the validity of the format string over again.
     #[inline]
     pub fn prepare(spec: &'s str) -> Result<Self, Error> {
-        parse(spec, &mut DelayedParse::<T>(PhantomData))
-            .map(|result| PreparedFormat { inner: result })
+        parse(spec, &mut DelayedParse::<T>(PhantomData), &Limits::default())
+            .map(|result| PreparedFormat { inner: result, count_limits: Vec::new() })
     }
 
-    /// Append a linefeed (`\n`) to the end of this buffer.
-    #[inline]
-    pub fn newln(&mut self) -> &mut Self {
-        self.inner.newln();
-        self
+    /// Prepare a format string against a formattable type, rejecting literal
+    /// widths and precisions beyond the given limits with `CountTooLarge`.
+    ///
+    /// Widths and precisions taken from the value can only be checked once
+    /// it is formatted, when there is no way to fail, so they are clamped to
+    /// the limits instead.
+    pub fn prepare_with_limits(spec: &'s str, limits: Limits) -> Result<Self, Error<'s>> {
+        let inner = parse(spec, &mut DelayedParse::<T>(PhantomData), &limits)?;
+        let mut count_limits: Vec<(usize, usize)> = Vec::new();
+        {
+            // a count used as both width and precision takes the lower limit
+            let mut clamp = |count, limit| if let v1::Count::Param(pos) = count {
+                match count_limits.iter_mut().find(|c| c.0 == pos) {
+                    Some(c) => c.1 = ::core::cmp::min(c.1, limit),
+                    None => count_limits.push((pos, limit)),
+                }
+            };
+            for arg in inner.fmt.iter().flat_map(|fmt| fmt) {
+                clamp(arg.format.width, limits.max_width);
+                clamp(arg.format.precision, limits.max_precision);
+            }
+        }
+        count_limits.retain(|c| c.1 != usize::max_value());
+        Ok(PreparedFormat { inner: inner, count_limits: count_limits })
     }
 
-    /// Call a function accepting `Arguments` with the contents of this buffer.
-    pub fn with<F: FnOnce(Arguments) -> R, R>(&self, t: &T, f: F) -> R {
-        let pieces = self.inner.pieces();
-        let args: Vec<ArgumentV1> = self.inner.args.iter().map(|f| match *f {
-            PreparedArgument::Normal(func) => ArgumentV1::new(t, func),
-            PreparedArgument::Usize(func) => ArgumentV1::from_usize(func(t)),
-        }).collect();
-        f(match self.inner.fmt {
-            Some(ref fmt) => Arguments::new_v1_formatted(&pieces, &args, fmt),
-            None => Arguments::new_v1(&pieces, &args),
-        })
+    /// Prepare a format string against a formattable type, interpreting its
+    /// alignment for text running in the given direction.
+    #[inline]
+    pub fn prepare_with_direction(spec: &'s str, direction: Direction) -> Result<Self, Error<'s>> {
+        parse_directed(spec, &mut DelayedParse::<T>(PhantomData), &Limits::default(), direction)
+            .map(|result| PreparedFormat { inner: result, count_limits: Vec::new() })
     }
 
-    /// Format the given value to a `String`.
+    /// Prepare a format string against a formattable type, with arguments
+    /// opened and closed by the given characters rather than by braces.
+    ///
+    /// Arguments are written as `<name:>5>` with `'<'` and `'>'`, and within
+    /// them the syntax is otherwise unchanged. Braces are ordinary text, and
+    /// the delimiters themselves are escaped by doubling them, as in `<<`.
+    /// A close delimiter which is also an alignment is read as one where an
+    /// alignment may appear, so `<:>5>` is right-aligned but `<:>>` is left
+    /// unclosed. `spec` returns the format string with its own delimiters.
+    ///
+    /// The open and close delimiters should differ, or an argument could
+    /// not be told apart from an escaped delimiter.
     #[inline]
-    pub fn format(&self, t: &T) -> String {
-        self.with(t, ::std::fmt::format)
+    pub fn prepare_with_delims(spec: &'s str, open: char, close: char)
+        -> Result<Self, Error<'s>>
+    {
+        let mut target = DelayedParse::<T>(PhantomData);
+        let limits = Limits::default();
+        parse_collecting(spec, &mut target, false, &limits, Direction::default(), (open, close))
+            .map_err(|mut errors| errors.remove(0))
+            .map(|result| PreparedFormat { inner: result, count_limits: Vec::new() })
     }
 
-    /// Print the given value to standard output.
+    /// Prepare a format string against a formattable type, reporting every
+    /// bad name, index, or specifier rather than stopping at the first.
+    ///
+    /// Syntax errors are still reported alone as a single `BadSyntax`.
     #[inline]
-    pub fn print(&self, t: &T) {
-        self.with(t, _print)
+    pub fn prepare_collecting(spec: &'s str) -> Result<Self, Vec<Error>> {
+        let mut target = DelayedParse::<T>(PhantomData);
+        parse_collecting(spec, &mut target, true, &Limits::default(), Direction::default(), BRACES)
+            .map(|result| PreparedFormat { inner: result, count_limits: Vec::new() })
     }
 
-    /// Write the given value to an `io::Write`.
+    /// Detach this prepared format from the lifetime of its format string by
+    /// copying any borrowed text.
+    pub fn into_owned(self) -> PreparedFormat<'static, T> {
+        let Parsed { spec, pieces, args, slots, fmt, names, indices, .. } = self.inner;
+        PreparedFormat {
+            inner: Parsed {
+                spec: Cow::Owned(spec.into_owned()),
+                pieces: pieces.into_iter().map(|p| Cow::Owned(p.into_owned())).collect(),
+                borrowed: None,
+                args: args,
+                slots: slots.into_iter()
+                    .map(|(idx, ty)| (idx, ty.map(|ty| Cow::Owned(ty.into_owned()))))
+                    .collect(),
+                fmt: fmt,
+                names: names.into_iter().map(|n| Cow::Owned(n.into_owned())).collect(),
+                indices: indices,
+            },
+            count_limits: self.count_limits,
+        }
+    }
+
+    /// Bind this prepared format to another type without parsing the format
+    /// string again, checking each argument against that type as `prepare`
+    /// would.
+    ///
+    /// Names are looked up again in the new type, so the two types need only
+    /// agree on the names used. An index which this type also gives one of
+    /// the referenced names is taken to mean that name.
+    pub fn rebind<U: FormatArgs>(&self) -> Result<PreparedFormat<'s, U>, Error> {
+        let inner = &self.inner;
+        let mut moved = Vec::new();
+        for name in &inner.names {
+            let from = T::validate_name(name).ok_or_else(|| Error::BadName(name.clone()))?;
+            let to = U::validate_name(name).ok_or_else(|| Error::BadName(name.clone()))?;
+            moved.push((from, to));
+        }
+        let mut args = Vec::with_capacity(inner.slots.len());
+        let mut slots = Vec::with_capacity(inner.slots.len());
+        let mut indices = Vec::new();
+        for &(idx, ref ty) in &inner.slots {
+            let idx = match moved.iter().find(|m| m.0 == idx) {
+                Some(&(_, to)) => to,
+                None if U::validate_index(idx) => idx,
+                None => return Err(Error::BadIndex(idx)),
+            };
+            args.push(match *ty {
+                Some(ref ty) => PreparedArgument::format(ty, idx)?,
+                None => PreparedArgument::format_usize(idx)
+                    .map_err(|reason| Error::BadCount { idx: idx, ty: None, reason: reason })?,
+            });
+            slots.push((idx, ty.clone()));
+            if let Err(pos) = indices.binary_search(&idx) {
+                indices.insert(pos, idx);
+            }
+        }
+        Ok(PreparedFormat {
+            inner: Parsed {
+                spec: inner.spec.clone(),
+                pieces: inner.pieces.clone(),
+                borrowed: inner.borrowed.clone(),
+                args: args,
+                slots: slots,
+                fmt: inner.fmt.clone(),
+                names: inner.names.clone(),
+                indices: indices,
+            },
+            count_limits: self.count_limits.clone(),
+        })
+    }
+
+    /// Return the format string this was prepared from, including any text
+    /// added by `newln` or `append`, with its braces escaped.
     #[inline]
-    pub fn write_io<W: io::Write + ?Sized>(&self, t: &T, dest: &mut W) -> io::Result<()> {
-        self.with(t, |args| dest.write_fmt(args))
+    pub fn spec(&self) -> &str {
+        &self.inner.spec
     }
 
-    /// Write the given value to a `fmt::Write`.
+    /// Iterate over the names this format string refers to, in order of first
+    /// appearance, including those used as counts.
     #[inline]
-    pub fn write_fmt<W: fmt::Write + ?Sized>(&self, t: &T, dest: &mut W) -> fmt::Result {
-        self.with(t, |args| dest.write_fmt(args))
+    pub fn referenced_names(&self) -> impl Iterator<Item=&str> {
+        self.inner.names.iter().map(|r| &**r)
     }
-}
 
-impl<'s, T: FormatArgs> Clone for PreparedFormat<'s, T> {
-    fn clone(&self) -> Self {
-        PreparedFormat { inner: self.inner.clone() }
+    /// Return the indices of every argument this format string refers to,
+    /// whether by position or by name, in ascending order.
+    #[inline]
+    pub fn referenced_indices(&self) -> &[usize] {
+        &self.inner.indices
     }
-    fn clone_from(&mut self, source: &Self) {
-        self.inner.clone_from(&source.inner)
+
+    /// Return the number of arguments this format refers to, including those
+    /// used as counts.
+    ///
+    /// A name and an index which resolve to the same child, formatted with
+    /// the same format type, are one argument.
+    #[inline]
+    pub fn arg_count(&self) -> usize {
+        self.inner.args.len()
     }
-}
 
-/// A buffer representing a parsed format string and arguments.
-#[derive(Clone)]
-pub struct FormatBuf<'s> {
-    inner: Parsed<'s, ImmediateParse<'s>>,
-}
+    /// Return whether all of the literal text is borrowed, rather than some
+    /// of it having been copied, as when it spans an escaped brace or was
+    /// added by `append`.
+    #[inline]
+    pub fn is_fully_borrowed(&self) -> bool {
+        self.inner.is_fully_borrowed()
+    }
 
-impl<'s> FormatBuf<'s> {
-    /// Construct a new buffer from the given format string and arguments.
+    /// Check a format string against a formattable type without preparing
+    /// it for use.
     ///
-    /// This method should usually not be called directly. Instead use the
-    /// `rt_format_args!` macro.
+    /// This performs the same checks as `prepare`, but discards the result
+    /// rather than keeping the formatting functions for each argument.
     #[inline]
-    pub fn new(spec: &'s str, params: &'s [Param<'s>]) -> Result<Self, Error<'s>> {
-        parse(spec, &mut ImmediateParse(params))
-            .map(|result| FormatBuf { inner: result })
+    pub fn validate(spec: &str) -> Result<(), Error> {
+        parse(spec, &mut ValidateOnly::<T>(DelayedParse(PhantomData)), &Limits::default())
+            .map(|_| ())
     }
 
     /// Append a linefeed (`\n`) to the end of this buffer.
@@ -260,81 +1018,1182 @@ impl<'s> FormatBuf<'s> {
         self
     }
 
+    /// Append literal text to the end of this buffer, such as `\r\n` or
+    /// another record separator in place of the linefeed `newln` adds.
+    ///
+    /// Braces in the text are written as they are rather than starting an
+    /// argument.
+    #[inline]
+    pub fn append(&mut self, text: &str) -> &mut Self {
+        self.inner.append(String::from(text).into());
+        self
+    }
+
     /// Call a function accepting `Arguments` with the contents of this buffer.
-    pub fn with<F: FnOnce(Arguments) -> R, R>(&self, f: F) -> R {
-        let pieces = self.inner.pieces();
-        f(match self.inner.fmt {
-            Some(ref fmt) => Arguments::new_v1_formatted(&pieces, &self.inner.args, fmt),
-            None => Arguments::new_v1(&pieces, &self.inner.args),
+    ///
+    /// The argument list is kept on the stack when there are at most eight
+    /// arguments, and allocated otherwise.
+    #[inline]
+    pub fn with<F: FnOnce(Arguments) -> R, R>(&self, t: &T, f: F) -> R {
+        with_small(self.inner.args.len(), placeholder(), |i| self.argument(t, i), |args| {
+            self.clamp_counts(t, args);
+            self.call(args, f)
         })
     }
 
-    /// Format this buffer to a `String`.
-    #[inline]
-    pub fn format(&self) -> String {
-        self.with(::std::fmt::format)
+    /// Call a function accepting `Arguments` with the contents of this buffer,
+    /// using `buf` as scratch space for the argument list.
+    ///
+    /// Reusing the same `buf` across many calls avoids reallocating it each
+    /// time. Its previous contents are discarded.
+    pub fn with_buf<'a, F, R>(&'a self, t: &'a T, buf: &mut Vec<ArgumentV1<'a>>, f: F) -> R
+        where F: FnOnce(Arguments) -> R
+    {
+        buf.clear();
+        buf.extend((0..self.inner.args.len()).map(|i| self.argument(t, i)));
+        self.clamp_counts(t, buf);
+        self.call(buf, f)
     }
 
-    /// Print this buffer to standard output.
     #[inline]
-    pub fn print(&self) {
-        self.with(_print)
+    fn argument<'a>(&'a self, t: &'a T, i: usize) -> ArgumentV1<'a> {
+        self.inner.args[i].argument(t)
     }
 
-    /// Write this buffer to an `io::Write`.
-    #[inline]
-    pub fn write_io<W: io::Write + ?Sized>(&self, dest: &mut W) -> io::Result<()> {
-        self.with(|args| dest.write_fmt(args))
+    fn clamp_counts<'a>(&'a self, t: &'a T, args: &mut [ArgumentV1<'a>]) {
+        for &(pos, ref limit) in &self.count_limits {
+            if let Some(count) = self.inner.args[pos].count(t) {
+                if count > limit {
+                    args[pos] = ArgumentV1::from_usize(limit);
+                }
+            }
+        }
     }
 
-    /// Write this buffer to a `fmt::Write`.
+    #[inline]
+    fn call<F: FnOnce(Arguments) -> R, R>(&self, args: &[ArgumentV1], f: F) -> R {
+        section::resolved(args, &self.inner.slots, |args| self.inner.with_arguments(args, f))
+    }
+
+    /// Format the given value to a `String`.
+    ///
+    /// The `String` starts with room for the literal text, and a little
+    /// more if there are arguments.
+    #[inline]
+    pub fn format(&self, t: &T) -> String {
+        self.with(t, ::alloc::fmt::format)
+    }
+
+    /// Format the given value to a `String` which starts with room for
+    /// `capacity` bytes, for output whose size is known to be large.
+    #[inline]
+    pub fn format_with_capacity(&self, t: &T, capacity: usize) -> String {
+        let mut dest = String::with_capacity(capacity);
+        self.format_into(t, &mut dest);
+        dest
+    }
+
+    /// Format the given value onto the end of an existing `String`.
+    #[inline]
+    pub fn format_into(&self, t: &T, dest: &mut String) {
+        self.write_fmt(t, dest).expect("a formatting trait implementation returned an error")
+    }
+
+    /// Format each of the given values to a `String` of its own.
+    ///
+    /// The values may be given either by reference or by value.
+    pub fn format_all<I>(&self, items: I) -> Vec<String>
+        where I: IntoIterator, I::Item: Borrow<T>
+    {
+        items.into_iter().map(|t| self.format(t.borrow())).collect()
+    }
+
+    /// Format each of the given values onto the end of an existing `String`,
+    /// with a linefeed (`\n`) between each and the next.
+    pub fn format_all_into<I>(&self, items: I, dest: &mut String)
+        where I: IntoIterator, I::Item: Borrow<T>
+    {
+        for (i, t) in items.into_iter().enumerate() {
+            if i > 0 {
+                dest.push('\n');
+            }
+            self.format_into(t.borrow(), dest);
+        }
+    }
+
+    /// Print the given value to standard output.
+    #[inline]
+    #[cfg(feature = "std")]
+    pub fn print(&self, t: &T) {
+        self.with(t, _print)
+    }
+
+    /// Write the given value to an `io::Write`.
+    #[inline]
+    #[cfg(feature = "std")]
+    pub fn write_io<W: io::Write + ?Sized>(&self, t: &T, dest: &mut W) -> io::Result<()> {
+        self.with(t, |args| dest.write_fmt(args))
+    }
+
+    /// Write the given value to an `io::Write` with a single `write_all`,
+    /// formatting it in memory first.
+    ///
+    /// `write_io` instead passes each piece of output to the writer as it
+    /// is produced, which for an unbuffered writer means many small writes.
+    #[cfg(feature = "std")]
+    pub fn write_io_buffered<W: io::Write + ?Sized>(&self, t: &T, dest: &mut W) -> io::Result<()> {
+        let mut buf = String::new();
+        self.write_fmt(t, &mut buf).map_err(fmt_error)?;
+        dest.write_all(buf.as_bytes())
+    }
+
+    /// Write the given value to a cursor over a fixed buffer only if all of
+    /// it fits in the space remaining.
+    ///
+    /// If it does not, nothing is written, and an error holding `DoesNotFit`
+    /// is returned.
+    #[cfg(feature = "std")]
+    pub fn write_io_exact(&self, t: &T, dest: &mut io::Cursor<&mut [u8]>) -> io::Result<()> {
+        let mut buf = String::new();
+        self.write_fmt(t, &mut buf).map_err(fmt_error)?;
+        write_exact(&buf, dest)
+    }
+
+    /// Write the given value to an `io::Write` in pieces of `chunk_size`
+    /// bytes, so that no more than that is held in memory at once.
+    ///
+    /// Each write but the last is of exactly `chunk_size` bytes, which may
+    /// split a character between writes.
+    ///
+    /// # Panics
+    ///
+    /// If `chunk_size` is zero.
+    #[cfg(feature = "std")]
+    pub fn write_chunked<W>(&self, t: &T, dest: &mut W, chunk_size: usize) -> io::Result<()>
+        where W: io::Write + ?Sized
+    {
+        ChunkedWriter::run(dest, chunk_size, |w| self.write_fmt(t, w))
+    }
+
+    /// Write the given value to a `futures::io::AsyncWrite`.
+    ///
+    /// The value is formatted immediately, and the output written as the
+    /// returned future is polled.
+    #[inline]
+    #[cfg(feature = "async")]
+    pub fn write_async<'a, W>(&self, t: &T, dest: &'a mut W)
+        -> impl ::core::future::Future<Output=io::Result<()>> + 'a
+        where W: futures_io::AsyncWrite + Unpin + ?Sized, T: 'a
+    {
+        let mut buf = Vec::new();
+        let result = self.write_to_vec(t, &mut buf).map(|_| buf);
+        async_write::WriteAll::new(dest, result.map_err(fmt_error))
+    }
+
+    /// Write the given value to a `fmt::Write`.
+    #[inline]
+    pub fn write_fmt<W: fmt::Write + ?Sized>(&self, t: &T, dest: &mut W) -> fmt::Result {
+        self.with(t, |args| dest.write_fmt(args))
+    }
+
+    /// Write the given value to a `fmt::Write` with a single `write_str`,
+    /// formatting it in memory first so that nothing is written unless all
+    /// of it formats.
+    ///
+    /// On failure, the number of bytes formatted before the error is given,
+    /// including any written by the argument which failed. If it was the
+    /// destination which failed, that is the length of the whole output.
+    pub fn write_fmt_atomic<W>(&self, t: &T, dest: &mut W) -> Result<(), (usize, fmt::Error)>
+        where W: fmt::Write + ?Sized
+    {
+        let mut buf = String::new();
+        let result = self.write_fmt(t, &mut buf).and_then(|()| dest.write_str(&buf));
+        result.map_err(|e| (buf.len(), e))
+    }
+
+    /// Append the given value to a byte vector, returning the number of
+    /// bytes written.
+    #[inline]
+    pub fn write_to_vec(&self, t: &T, dest: &mut Vec<u8>) -> Result<usize, fmt::Error> {
+        let start = dest.len();
+        self.write_fmt(t, &mut VecWriter(dest))?;
+        Ok(dest.len() - start)
+    }
+
+    /// Format the given value as UTF-16, such as for Windows APIs.
+    ///
+    /// The output is encoded as it is produced, without a `String` between.
+    #[inline]
+    pub fn format_utf16(&self, t: &T) -> Vec<u16> {
+        let mut dest = Vec::new();
+        self.write_fmt(t, &mut Utf16Writer(&mut dest))
+            .expect("a formatting trait implementation returned an error");
+        dest
+    }
+
+    /// Return the length in bytes of the given value when formatted, without
+    /// allocating.
+    #[inline]
+    pub fn formatted_len(&self, t: &T) -> usize {
+        let mut counter = LenCounter(0);
+        self.write_fmt(t, &mut counter).expect("a formatting trait implementation returned an error");
+        counter.0
+    }
+
+    /// Pair this format with a value, for use within another format string.
+    #[inline]
+    pub fn display<'a>(&'a self, t: &'a T) -> PreparedDisplay<'a, 's, T> {
+        PreparedDisplay(self, t)
+    }
+
+    /// Pair this format with a value to be formatted only once it is written.
+    ///
+    /// Nothing is formatted until the result is written to some sink, so a
+    /// log message whose level is filtered out costs no more than making the
+    /// pair. The result borrows both this format and the value, and so
+    /// cannot outlive either.
+    #[inline]
+    pub fn deferred<'a>(&'a self, t: &'a T) -> impl fmt::Display + codegen::Captures<'s> + 'a {
+        PreparedDisplay(self, t)
+    }
+}
+
+impl<'s, T: FormatArgs> Clone for PreparedFormat<'s, T> {
+    fn clone(&self) -> Self {
+        PreparedFormat {
+            inner: self.inner.clone(),
+            count_limits: self.count_limits.clone(),
+        }
+    }
+    fn clone_from(&mut self, source: &Self) {
+        self.inner.clone_from(&source.inner);
+        self.count_limits.clone_from(&source.count_limits);
+    }
+}
+
+/// Prepares a format string, as with `PreparedFormat::prepare`.
+impl<'s, T: FormatArgs> TryFrom<&'s str> for PreparedFormat<'s, T> {
+    type Error = Error<'s>;
+
+    fn try_from(spec: &'s str) -> Result<Self, Error<'s>> {
+        PreparedFormat::prepare(spec)
+    }
+}
+
+/// A prepared format paired with a value, formatted as it is displayed.
+///
+/// Returned by `PreparedFormat::display`.
+pub struct PreparedDisplay<'a, 's: 'a, T: FormatArgs + 'a>(&'a PreparedFormat<'s, T>, &'a T);
+
+impl<'a, 's, T: FormatArgs> fmt::Display for PreparedDisplay<'a, 's, T> {
+    #[inline]
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        self.0.with(self.1, |args| fmt.write_fmt(args))
+    }
+}
+
+impl<'a, 's, T: FormatArgs> fmt::Debug for PreparedDisplay<'a, 's, T> {
+    #[inline]
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, fmt)
+    }
+}
+
+/// A buffer representing a parsed format string and arguments.
+///
+/// The buffer borrows the values of its parameters, including those made
+/// with `Param::owned`, which live in the parameter slice. It therefore
+/// cannot outlive that slice and has no `into_owned`. `new_owned` instead
+/// builds an `OwnedFormatBuf` which keeps its parameters with it. To keep a
+/// format string around and format it against many values, prepare it
+/// against a `FormatArgs` type instead; `PreparedFormat::into_owned`
+/// detaches it from the format string entirely.
+#[derive(Clone)]
+pub struct FormatBuf<'s> {
+    inner: Parsed<'s, ImmediateParse<'s>>,
+}
+
+impl<'s> FormatBuf<'s> {
+    /// Construct a new buffer from the given format string and arguments.
+    ///
+    /// This method should usually not be called directly. Instead use the
+    /// `rt_format_args!` macro.
+    #[inline]
+    pub fn new(spec: &'s str, params: &'s [Param<'s>]) -> Result<Self, Error<'s>> {
+        parse(spec, &mut ImmediateParse(params), &Limits::default())
+            .map(|result| FormatBuf { inner: result })
+    }
+
+    /// Construct a new buffer from the given format string and arguments,
+    /// rejecting widths and precisions beyond the given limits with
+    /// `CountTooLarge`.
+    #[inline]
+    pub fn new_with_limits(spec: &'s str, params: &'s [Param<'s>], limits: Limits)
+        -> Result<Self, Error<'s>>
+    {
+        parse(spec, &mut ImmediateParse(params), &limits)
+            .map(|result| FormatBuf { inner: result })
+    }
+
+    /// Construct a new buffer from the given format string and arguments,
+    /// interpreting its alignment for text running in the given direction.
+    #[inline]
+    pub fn new_with_direction(spec: &'s str, params: &'s [Param<'s>], direction: Direction)
+        -> Result<Self, Error<'s>>
+    {
+        parse_directed(spec, &mut ImmediateParse(params), &Limits::default(), direction)
+            .map(|result| FormatBuf { inner: result })
+    }
+
+    /// Construct a new buffer from the given format string and arguments,
+    /// reporting any named parameter it never refers to as `UnusedArgument`.
+    ///
+    /// A name given to more than one parameter, of which only the first
+    /// could ever be referred to, is reported as `DuplicateName`. Unused
+    /// nameless parameters are still ignored, as with `format!`.
+    pub fn new_strict(spec: &'s str, params: &'s [Param<'s>]) -> Result<Self, Error<'s>> {
+        for (idx, param) in params.iter().enumerate() {
+            if let Some(name) = param.name {
+                if params[..idx].iter().any(|earlier| earlier.name == Some(name)) {
+                    return Err(Error::DuplicateName(name.into()))
+                }
+            }
+        }
+        let inner = parse(spec, &mut ImmediateParse(params), &Limits::default())?;
+        for (idx, param) in params.iter().enumerate() {
+            if let Some(name) = param.name {
+                if inner.indices.binary_search(&idx).is_err() {
+                    return Err(Error::UnusedArgument { name: name.into() })
+                }
+            }
+        }
+        Ok(FormatBuf { inner: inner })
+    }
+
+    /// Construct a new buffer from the given format string and arguments,
+    /// reporting any parameter it never refers to.
+    ///
+    /// Named parameters are reported as by `new_strict`, and nameless ones as
+    /// `UnusedIndex`, so that a format string which has lost one of its `{}`
+    /// is caught.
+    pub fn new_exhaustive(spec: &'s str, params: &'s [Param<'s>]) -> Result<Self, Error<'s>> {
+        let buf = FormatBuf::new_strict(spec, params)?;
+        let unused = (0..params.len()).find(|idx| buf.inner.indices.binary_search(idx).is_err());
+        match unused {
+            Some(idx) => Err(Error::UnusedIndex(idx)),
+            None => Ok(buf),
+        }
+    }
+
+    /// Construct a new buffer from the given format string and arguments,
+    /// matching names without regard to ASCII case.
+    ///
+    /// A name which matches parameters of more than one spelling, such as
+    /// both `name` and `Name`, is reported as `AmbiguousName`.
+    #[inline]
+    pub fn new_ignore_case(spec: &'s str, params: &'s [Param<'s>]) -> Result<Self, Error<'s>> {
+        parse(spec, &mut IgnoreCase(ImmediateParse(params)), &Limits::default())
+            .map(|result| FormatBuf { inner: result.retarget() })
+    }
+
+    /// Construct a new buffer from the given format string and arguments,
+    /// formatting `default` in place of any name which matches none of them.
+    ///
+    /// The default may stand in for a value or a count, in which case it
+    /// must be usable as one; where it is not, the error is `Defaulted`,
+    /// naming the name it stood in for. Indices beyond the arguments given
+    /// are still reported as `MissingArgument`.
+    ///
+    /// ```rust
+    /// use runtime_fmt::{FormatBuf, Param};
+    ///
+    /// let params = [Param::named("name", &"Ann")];
+    /// let default = Param::normal(&"?");
+    /// let buf = FormatBuf::new_with_default("{name} ({age})", &params, &default).unwrap();
+    /// assert_eq!(buf.format(), "Ann (?)");
+    /// ```
+    #[inline]
+    pub fn new_with_default(spec: &'s str, params: &'s [Param<'s>], default: &'s Param<'s>)
+        -> Result<Self, Error<'s>>
+    {
+        let mut target = WithDefault {
+            params: ImmediateParse(params),
+            default: default,
+            missing: Vec::new(),
+        };
+        let mut inner = match parse(spec, &mut target, &Limits::default()) {
+            Ok(result) => result.retarget::<ImmediateParse>(),
+            Err(e) => return Err(e.defaulted(&target.missing)),
+        };
+        move_defaulted(&mut inner.slots, &mut inner.indices, &target.missing, &inner.names);
+        Ok(FormatBuf { inner: inner })
+    }
+
+    /// Construct a new buffer from the given format string and arguments,
+    /// reporting every bad name, index, or specifier rather than stopping at
+    /// the first.
+    ///
+    /// Syntax errors are still reported alone as a single `BadSyntax`.
+    #[inline]
+    pub fn new_collecting(spec: &'s str, params: &'s [Param<'s>])
+        -> Result<Self, Vec<Error<'s>>>
+    {
+        let mut target = ImmediateParse(params);
+        parse_collecting(spec, &mut target, true, &Limits::default(), Direction::default(), BRACES)
+            .map(|result| FormatBuf { inner: result })
+    }
+
+    /// Construct a buffer which takes ownership of its parameters, so that
+    /// it may be returned from where they were made.
+    ///
+    /// Parameters made with `Param::owned` leave the buffer borrowing only
+    /// the format string.
+    pub fn new_owned(spec: &'s str, params: Vec<Param<'s>>)
+        -> Result<OwnedFormatBuf<'s>, Error<'s>>
+    {
+        let mut target = OwnedParse(params);
+        let inner = parse(spec, &mut target, &Limits::default())?;
+        Ok(OwnedFormatBuf { inner: inner, params: target.0 })
+    }
+
+    /// Empty this buffer, keeping the space allocated for its contents so
+    /// that `reparse` may reuse it.
+    ///
+    /// An empty buffer formats as the empty string.
+    pub fn reset(&mut self) {
+        self.inner.clear();
+    }
+
+    /// Replace the contents of this buffer with the given format string and
+    /// arguments, as `new` would, reusing the space allocated for its
+    /// previous contents.
+    ///
+    /// Formatting a stream of records with one buffer reparsed for each
+    /// avoids allocating its lists anew every time. If an error is returned,
+    /// the buffer is left empty, as after `reset`.
+    pub fn reparse(&mut self, spec: &'s str, params: &'s [Param<'s>]) -> Result<(), Error<'s>> {
+        self.reset();
+        let emptied = ::core::mem::replace(&mut self.inner, Parsed::new());
+        self.inner = parse_reusing(spec, &mut ImmediateParse(params), emptied)?;
+        Ok(())
+    }
+
+    /// Append a linefeed (`\n`) to the end of this buffer.
+    #[inline]
+    pub fn newln(&mut self) -> &mut Self {
+        self.inner.newln();
+        self
+    }
+
+    /// Append literal text to the end of this buffer, such as `\r\n` or
+    /// another record separator in place of the linefeed `newln` adds.
+    ///
+    /// Braces in the text are written as they are rather than starting an
+    /// argument.
+    #[inline]
+    pub fn append(&mut self, text: &str) -> &mut Self {
+        self.inner.append(String::from(text).into());
+        self
+    }
+
+    /// Append another buffer to the end of this one, so that both are
+    /// formatted in a single pass.
+    pub fn concat(mut self, other: FormatBuf<'s>) -> FormatBuf<'s> {
+        self.inner.concat(other.inner);
+        self
+    }
+
+    /// Return the format string this buffer was made from, including any
+    /// text added by `newln` or `append`, with its braces escaped, and the
+    /// format strings of any buffers appended by `concat`.
+    ///
+    /// Positions in an appended format string still refer to its own
+    /// parameters, so the result of `concat` may not parse to the same
+    /// buffer again.
+    #[inline]
+    pub fn spec(&self) -> &str {
+        &self.inner.spec
+    }
+
+    /// Return the number of arguments this buffer refers to, including those
+    /// used as counts.
+    #[inline]
+    pub fn arg_count(&self) -> usize {
+        self.inner.args.len()
+    }
+
+    /// Return whether all of the literal text of this buffer is borrowed,
+    /// rather than some of it having been copied, as when it spans an escaped
+    /// brace or was added by `append`.
+    #[inline]
+    pub fn is_fully_borrowed(&self) -> bool {
+        self.inner.is_fully_borrowed()
+    }
+
+    /// Iterate over the literal text segments of this buffer.
+    #[inline]
+    pub fn pieces(&self) -> impl Iterator<Item=&str> {
+        self.inner.pieces.iter().map(|r| &**r)
+    }
+
+    /// Return the total length in bytes of the literal text, a lower bound on
+    /// the length of the formatted output.
+    ///
+    /// `format` already sizes its `String` from this, so it is mostly of use
+    /// when pre-sizing other buffers.
+    #[inline]
+    pub fn len_hint(&self) -> usize {
+        self.inner.pieces.iter().map(|p| p.len()).sum()
+    }
+
+    /// Find the argument whose formatting trait implementation fails, if
+    /// any, by formatting each one again alone.
+    ///
+    /// This tells an error raised while formatting an argument from one
+    /// raised by the destination written to. Where the argument was the
+    /// default of `new_with_default`, `defaulted_name` gives the name it
+    /// stood in for.
+    pub fn failing_argument(&self) -> Option<usize> {
+        use core::fmt::Write;
+
+        let inner = &self.inner;
+        let implicit;
+        let fmt = match inner.fmt {
+            Some(ref fmt) => fmt,
+            None => {
+                implicit = (0..inner.args.len()).map(|i| v1::Argument {
+                    position: i,
+                    format: DEFAULT_VALUE,
+                }).collect::<Vec<_>>();
+                &implicit
+            }
+        };
+        fmt.iter().find(|arg| {
+            let alone = [(*arg).clone()];
+            let args = Arguments::new_v1_formatted(&[""], &inner.args, &alone);
+            LenCounter(0).write_fmt(args).is_err()
+        }).map(|arg| inner.slots[arg.position].0)
+    }
+
+    /// Return the name which the default of `new_with_default` stood in for
+    /// as the argument at `idx`, or `None` if a parameter is at `idx`.
+    ///
+    /// Such arguments have indices past every parameter, one for each name.
+    #[inline]
+    pub fn defaulted_name(&self, idx: usize) -> Option<&str> {
+        defaulted_name(&self.inner.names, idx).map(|name| &**name)
+    }
+
+    // Convert an error from writing this buffer, naming the failing argument
+    // if there is one.
+    #[doc(hidden)]
+    pub fn _write_error<'e, E: Into<Error<'e>>>(&self, e: E) -> Error<'e> {
+        match e.into() {
+            Error::Fmt(source) => match self.failing_argument() {
+                Some(idx) => match self.defaulted_name(idx) {
+                    Some(name) => Error::Defaulted {
+                        name: Cow::Owned(name.into()),
+                        source: Box::new(Error::FmtAt { idx: 0, source: source }),
+                    },
+                    None => Error::FmtAt { idx: idx, source: source },
+                },
+                None => Error::Fmt(source),
+            },
+            e => e,
+        }
+    }
+
+    /// Call a function accepting `Arguments` with the contents of this buffer.
+    pub fn with<F: FnOnce(Arguments) -> R, R>(&self, f: F) -> R {
+        let inner = &self.inner;
+        section::resolved(&inner.args, &inner.slots, |args| inner.with_arguments(args, f))
+    }
+
+    /// Format this buffer to a `String`.
+    ///
+    /// The `String` starts with room for the literal text, as given by
+    /// `len_hint`.
+    #[inline]
+    pub fn format(&self) -> String {
+        self.format_with_capacity(self.len_hint())
+    }
+
+    /// Format this buffer to a `String` which starts with room for
+    /// `capacity` bytes, for output whose size is known to be large.
+    #[inline]
+    pub fn format_with_capacity(&self, capacity: usize) -> String {
+        let mut dest = String::with_capacity(capacity);
+        self.format_into(&mut dest);
+        dest
+    }
+
+    /// Format this buffer onto the end of an existing `String`.
+    #[inline]
+    pub fn format_into(&self, dest: &mut String) {
+        self.write_fmt(dest).expect("a formatting trait implementation returned an error")
+    }
+
+    /// Print this buffer to standard output.
+    #[inline]
+    #[cfg(feature = "std")]
+    pub fn print(&self) {
+        self.with(_print)
+    }
+
+    /// Write this buffer to an `io::Write`.
+    #[inline]
+    #[cfg(feature = "std")]
+    pub fn write_io<W: io::Write + ?Sized>(&self, dest: &mut W) -> io::Result<()> {
+        self.with(|args| dest.write_fmt(args))
+    }
+
+    /// Write this buffer to an `io::Write` with a single `write_all`,
+    /// formatting it in memory first.
+    ///
+    /// `write_io` instead passes each piece of output to the writer as it
+    /// is produced, which for an unbuffered writer means many small writes.
+    #[cfg(feature = "std")]
+    pub fn write_io_buffered<W: io::Write + ?Sized>(&self, dest: &mut W) -> io::Result<()> {
+        let mut buf = String::new();
+        self.write_fmt(&mut buf).map_err(fmt_error)?;
+        dest.write_all(buf.as_bytes())
+    }
+
+    /// Write this buffer to a cursor over a fixed buffer only if all of it
+    /// fits in the space remaining.
+    ///
+    /// If it does not, nothing is written, and an error holding `DoesNotFit`
+    /// is returned.
+    #[cfg(feature = "std")]
+    pub fn write_io_exact(&self, dest: &mut io::Cursor<&mut [u8]>) -> io::Result<()> {
+        let mut buf = String::new();
+        self.write_fmt(&mut buf).map_err(fmt_error)?;
+        write_exact(&buf, dest)
+    }
+
+    /// Write this buffer to an `io::Write` in pieces of `chunk_size` bytes,
+    /// so that no more than that is held in memory at once.
+    ///
+    /// Each write but the last is of exactly `chunk_size` bytes, which may
+    /// split a character between writes.
+    ///
+    /// # Panics
+    ///
+    /// If `chunk_size` is zero.
+    #[cfg(feature = "std")]
+    pub fn write_chunked<W>(&self, dest: &mut W, chunk_size: usize) -> io::Result<()>
+        where W: io::Write + ?Sized
+    {
+        ChunkedWriter::run(dest, chunk_size, |w| self.write_fmt(w))
+    }
+
+    /// Write this buffer to a `futures::io::AsyncWrite`.
+    ///
+    /// The buffer is formatted immediately, and the output written as the
+    /// returned future is polled.
+    #[inline]
+    #[cfg(feature = "async")]
+    pub fn write_async<'a, W>(&self, dest: &'a mut W)
+        -> impl ::core::future::Future<Output=io::Result<()>> + 'a
+        where W: futures_io::AsyncWrite + Unpin + ?Sized
+    {
+        let mut buf = Vec::new();
+        let result = self.write_to_vec(&mut buf).map(|_| buf);
+        async_write::WriteAll::new(dest, result.map_err(fmt_error))
+    }
+
+    /// Write this buffer to a `fmt::Write`.
+    #[inline]
+    pub fn write_fmt<W: fmt::Write + ?Sized>(&self, dest: &mut W) -> fmt::Result {
+        self.with(|args| dest.write_fmt(args))
+    }
+
+    /// Write this buffer to a `fmt::Write` with a single `write_str`,
+    /// formatting it in memory first so that nothing is written unless all
+    /// of it formats.
+    ///
+    /// On failure, the number of bytes formatted before the error is given,
+    /// including any written by the argument which failed. If it was the
+    /// destination which failed, that is the length of the whole output.
+    pub fn write_fmt_atomic<W>(&self, dest: &mut W) -> Result<(), (usize, fmt::Error)>
+        where W: fmt::Write + ?Sized
+    {
+        let mut buf = String::new();
+        let result = self.write_fmt(&mut buf).and_then(|()| dest.write_str(&buf));
+        result.map_err(|e| (buf.len(), e))
+    }
+
+    /// Append this buffer to a byte vector, returning the number of bytes
+    /// written.
+    #[inline]
+    pub fn write_to_vec(&self, dest: &mut Vec<u8>) -> Result<usize, fmt::Error> {
+        let start = dest.len();
+        self.write_fmt(&mut VecWriter(dest))?;
+        Ok(dest.len() - start)
+    }
+
+    /// Format this buffer as UTF-16, such as for Windows APIs.
+    ///
+    /// The output is encoded as it is produced, without a `String` between.
+    #[inline]
+    pub fn format_utf16(&self) -> Vec<u16> {
+        let mut dest = Vec::new();
+        self.write_fmt(&mut Utf16Writer(&mut dest))
+            .expect("a formatting trait implementation returned an error");
+        dest
+    }
+
+    /// Return the length in bytes of this buffer when formatted, without
+    /// allocating.
+    #[inline]
+    pub fn formatted_len(&self) -> usize {
+        let mut counter = LenCounter(0);
+        self.write_fmt(&mut counter).expect("a formatting trait implementation returned an error");
+        counter.0
+    }
+}
+
+/// A buffer representing a parsed format string and the parameters it
+/// owns.
+///
+/// Returned by `FormatBuf::new_owned`. The formatting functions for each
+/// argument are looked up again every time the buffer is formatted, as the
+/// parameters may move along with the buffer.
+pub struct OwnedFormatBuf<'s> {
+    inner: Parsed<'s, OwnedParse<'s>>,
+    params: Vec<Param<'s>>,
+}
+
+impl<'s> OwnedFormatBuf<'s> {
+    /// Append a linefeed (`\n`) to the end of this buffer.
+    #[inline]
+    pub fn newln(&mut self) -> &mut Self {
+        self.inner.newln();
+        self
+    }
+
+    /// Append literal text to the end of this buffer, such as `\r\n` or
+    /// another record separator in place of the linefeed `newln` adds.
+    ///
+    /// Braces in the text are written as they are rather than starting an
+    /// argument.
+    #[inline]
+    pub fn append(&mut self, text: &str) -> &mut Self {
+        self.inner.append(String::from(text).into());
+        self
+    }
+
+    /// Return the format string this buffer was made from, including any
+    /// text added by `newln` or `append`, with its braces escaped.
+    #[inline]
+    pub fn spec(&self) -> &str {
+        &self.inner.spec
+    }
+
+    /// Return the number of arguments this buffer refers to, including those
+    /// used as counts.
+    #[inline]
+    pub fn arg_count(&self) -> usize {
+        self.inner.slots.len()
+    }
+
+    /// Call a function accepting `Arguments` with the contents of this buffer.
+    pub fn with<F: FnOnce(Arguments) -> R, R>(&self, f: F) -> R {
+        let argument = |i: usize| {
+            let (idx, ref ty) = self.inner.slots[i];
+            let param = &self.params[idx];
+            match *ty {
+                Some(ref ty) => param.value().by_name(ty, idx).ok(),
+                None => param.as_usize.as_ref().ok().map(ArgumentV1::from_usize),
+            }.expect("parameters were checked when the buffer was built")
+        };
+        with_small(self.inner.slots.len(), placeholder(), argument, |args| {
+            section::resolved(args, &self.inner.slots, |args| self.inner.with_arguments(args, f))
+        })
+    }
+
+    /// Format this buffer to a `String`.
+    #[inline]
+    pub fn format(&self) -> String {
+        self.with(::alloc::fmt::format)
+    }
+
+    /// Print this buffer to standard output.
+    #[inline]
+    #[cfg(feature = "std")]
+    pub fn print(&self) {
+        self.with(_print)
+    }
+
+    /// Write this buffer to an `io::Write`.
+    #[inline]
+    #[cfg(feature = "std")]
+    pub fn write_io<W: io::Write + ?Sized>(&self, dest: &mut W) -> io::Result<()> {
+        self.with(|args| dest.write_fmt(args))
+    }
+
+    /// Write this buffer to a `fmt::Write`.
     #[inline]
     pub fn write_fmt<W: fmt::Write + ?Sized>(&self, dest: &mut W) -> fmt::Result {
         self.with(|args| dest.write_fmt(args))
     }
-}
+}
+
+// Appends formatted output to a byte vector, bypassing `String`.
+struct VecWriter<'a>(&'a mut Vec<u8>);
+
+impl<'a> fmt::Write for VecWriter<'a> {
+    #[inline]
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0.extend_from_slice(s.as_bytes());
+        Ok(())
+    }
+}
+
+// Appends formatted output to a vector of UTF-16 code units.
+struct Utf16Writer<'a>(&'a mut Vec<u16>);
+
+impl<'a> fmt::Write for Utf16Writer<'a> {
+    #[inline]
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0.extend(s.encode_utf16());
+        Ok(())
+    }
+}
+
+// Convert a formatting error as `io::Write::write_fmt` does.
+#[cfg(feature = "std")]
+fn fmt_error(_: fmt::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, "formatter error")
+}
+
+// Write all of `buf` to `dest`, or none of it if it would not fit.
+#[cfg(feature = "std")]
+fn write_exact(buf: &str, dest: &mut io::Cursor<&mut [u8]>) -> io::Result<()> {
+    use std::io::Write;
+
+    let remaining = (dest.get_ref().len() as u64).saturating_sub(dest.position());
+    if buf.len() as u64 > remaining {
+        let fit = DoesNotFit { len: buf.len(), remaining: remaining as usize };
+        return Err(io::Error::new(io::ErrorKind::Other, fit))
+    }
+    dest.write_all(buf.as_bytes())
+}
+
+// Collects formatted output into a buffer of bounded size, passing it on to
+// an `io::Write` each time the buffer fills.
+#[cfg(feature = "std")]
+struct ChunkedWriter<'a, W: io::Write + ?Sized + 'a> {
+    dest: &'a mut W,
+    buf: Vec<u8>,
+    chunk_size: usize,
+    error: Option<io::Error>,
+}
+
+#[cfg(feature = "std")]
+impl<'a, W: io::Write + ?Sized> ChunkedWriter<'a, W> {
+    fn run<F>(dest: &'a mut W, chunk_size: usize, f: F) -> io::Result<()>
+        where F: FnOnce(&mut Self) -> fmt::Result
+    {
+        assert!(chunk_size > 0, "chunk size must be greater than zero");
+        let mut writer = ChunkedWriter {
+            dest: dest,
+            buf: Vec::with_capacity(chunk_size),
+            chunk_size: chunk_size,
+            error: None,
+        };
+        match (f(&mut writer), writer.error) {
+            (_, Some(e)) => Err(e),
+            (Err(e), None) => Err(fmt_error(e)),
+            (Ok(()), None) if writer.buf.is_empty() => Ok(()),
+            (Ok(()), None) => writer.dest.write_all(&writer.buf),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<'a, W: io::Write + ?Sized> fmt::Write for ChunkedWriter<'a, W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let mut bytes = s.as_bytes();
+        while !bytes.is_empty() {
+            let take = ::core::cmp::min(self.chunk_size - self.buf.len(), bytes.len());
+            self.buf.extend_from_slice(&bytes[..take]);
+            bytes = &bytes[take..];
+            if self.buf.len() == self.chunk_size {
+                if let Err(e) = self.dest.write_all(&self.buf) {
+                    self.error = Some(e);
+                    return Err(fmt::Error)
+                }
+                self.buf.clear();
+            }
+        }
+        Ok(())
+    }
+}
+
+// Counts the bytes of formatted output and discards them.
+struct LenCounter(usize);
+
+impl fmt::Write for LenCounter {
+    #[inline]
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0 += s.len();
+        Ok(())
+    }
+}
+
+impl<'a> fmt::Display for FormatBuf<'a> {
+    #[inline]
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        self.with(|args| fmt.write_fmt(args))
+    }
+}
+
+impl<'a> fmt::Debug for FormatBuf<'a> {
+    #[inline]
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, fmt)
+    }
+}
+
+impl<'a> fmt::Display for OwnedFormatBuf<'a> {
+    #[inline]
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        self.with(|args| fmt.write_fmt(args))
+    }
+}
+
+impl<'a> fmt::Debug for OwnedFormatBuf<'a> {
+    #[inline]
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, fmt)
+    }
+}
+
+trait ParseTarget<'p> {
+    type Argument;
+    fn validate_name(&mut self, name: &str) -> Option<usize>;
+    fn resolve_name<'s>(&mut self, name: &'s str) -> Result<usize, Error<'s>> {
+        self.validate_name(name).ok_or_else(|| Error::BadName(name.into()))
+    }
+    fn validate_index(&mut self, index: usize) -> bool;
+    fn resolve_index<'s>(&mut self, index: usize) -> Result<usize, Error<'s>> {
+        if self.validate_index(index) {
+            Ok(index)
+        } else {
+            Err(Error::BadIndex(index))
+        }
+    }
+    fn format<'s>(&mut self, spec: &'s str, idx: usize) -> Result<Self::Argument, Error<'s>>;
+    fn format_usize(&mut self, idx: usize) -> Result<Self::Argument, CountError>;
+    // The value of a count, if it is known while parsing.
+    fn count(&mut self, idx: usize) -> Option<usize>;
+    // The type of an argument, if it is known while parsing.
+    fn type_name(&mut self, _: usize) -> Option<&'static str> {
+        None
+    }
+}
+
+struct ImmediateParse<'p>(&'p [Param<'p>]);
+
+impl<'p> ImmediateParse<'p> {
+    // Indices are validated before use, but check again rather than panic.
+    #[inline]
+    fn get<'s>(&self, idx: usize) -> Result<&'p Param<'p>, Error<'s>> {
+        self.0.get(idx).ok_or(Error::BadIndex(idx))
+    }
+}
+
+impl<'p> ParseTarget<'p> for ImmediateParse<'p> {
+    type Argument = ArgumentV1<'p>;
+
+    fn validate_name(&mut self, name: &str) -> Option<usize> {
+        self.0.iter().position(|p| p.name.map_or(false, |n| n == name))
+    }
+
+    fn validate_index(&mut self, index: usize) -> bool {
+        index < self.0.len()
+    }
+
+    fn resolve_index<'s>(&mut self, index: usize) -> Result<usize, Error<'s>> {
+        if self.validate_index(index) {
+            Ok(index)
+        } else {
+            Err(Error::MissingArgument { index: index, supplied: self.0.len() })
+        }
+    }
+
+    fn format<'s>(&mut self, spec: &'s str, idx: usize) -> Result<Self::Argument, Error<'s>> {
+        self.get(idx)?.value().by_name(spec, idx)
+    }
+
+    fn format_usize(&mut self, idx: usize) -> Result<Self::Argument, CountError> {
+        // a missing parameter is no more use as a count than a string is
+        match self.0.get(idx).map(|param| &param.as_usize) {
+            Some(&Ok(ref value)) => Ok(ArgumentV1::from_usize(value)),
+            Some(&Err(reason)) => Err(reason),
+            None => Err(CountError::WrongType),
+        }
+    }
+
+    fn count(&mut self, idx: usize) -> Option<usize> {
+        self.0.get(idx)?.as_usize.ok()
+    }
+
+    fn type_name(&mut self, idx: usize) -> Option<&'static str> {
+        self.0.get(idx).map(|param| param.value().type_name())
+    }
+}
+
+// Matches names without regard to ASCII case, for `new_ignore_case`.
+struct IgnoreCase<'p>(ImmediateParse<'p>);
+
+impl<'p> ParseTarget<'p> for IgnoreCase<'p> {
+    type Argument = ArgumentV1<'p>;
+
+    fn validate_name(&mut self, name: &str) -> Option<usize> {
+        self.resolve_name(name).ok()
+    }
+
+    fn resolve_name<'s>(&mut self, name: &'s str) -> Result<usize, Error<'s>> {
+        let mut matches = (self.0).0.iter().enumerate()
+            .filter(|&(_, p)| p.name.map_or(false, |n| n.eq_ignore_ascii_case(name)));
+        let (idx, first) = match matches.next() {
+            Some((idx, param)) => (idx, param.name),
+            None => return Err(Error::BadName(name.into())),
+        };
+        // repeats of the same name resolve to the first, as usual
+        if matches.any(|(_, p)| p.name != first) {
+            return Err(Error::AmbiguousName(name.into()))
+        }
+        Ok(idx)
+    }
+
+    fn validate_index(&mut self, index: usize) -> bool {
+        self.0.validate_index(index)
+    }
+
+    fn resolve_index<'s>(&mut self, index: usize) -> Result<usize, Error<'s>> {
+        self.0.resolve_index(index)
+    }
+
+    fn format<'s>(&mut self, spec: &'s str, idx: usize) -> Result<Self::Argument, Error<'s>> {
+        self.0.format(spec, idx)
+    }
+
+    fn format_usize(&mut self, idx: usize) -> Result<Self::Argument, CountError> {
+        self.0.format_usize(idx)
+    }
 
-impl<'a> fmt::Display for FormatBuf<'a> {
-    #[inline]
-    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
-        self.with(|args| fmt.write_fmt(args))
+    fn count(&mut self, idx: usize) -> Option<usize> {
+        self.0.count(idx)
     }
-}
 
-impl<'a> fmt::Debug for FormatBuf<'a> {
-    #[inline]
-    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
-        fmt::Display::fmt(self, fmt)
+    fn type_name(&mut self, idx: usize) -> Option<&'static str> {
+        self.0.type_name(idx)
     }
 }
 
-trait ParseTarget<'p> {
-    type Argument;
-    fn validate_name(&mut self, name: &str) -> Option<usize>;
-    fn validate_index(&mut self, index: usize) -> bool;
-    fn format<'s>(&mut self, spec: &'s str, idx: usize) -> Result<Self::Argument, Error<'s>>;
-    fn format_usize(&mut self, idx: usize) -> Option<Self::Argument>;
+// Stands a default in for names which match no parameter, for
+// `new_with_default`. Each such name is given an index counting down from
+// `usize::MAX`, by its place among `missing` while parsing and among the
+// names referred to once parsed, so that none is taken for a parameter.
+struct WithDefault<'p> {
+    params: ImmediateParse<'p>,
+    default: &'p Param<'p>,
+    missing: Vec<Cow<'p, str>>,
 }
 
-struct ImmediateParse<'p>(&'p [Param<'p>]);
+impl<'p> WithDefault<'p> {
+    #[inline]
+    fn param<'s>(&self, idx: usize) -> Result<&'p Param<'p>, Error<'s>> {
+        match defaulted_name(&self.missing, idx) {
+            Some(_) => Ok(self.default),
+            None => self.params.get(idx),
+        }
+    }
+}
 
-impl<'p> ParseTarget<'p> for ImmediateParse<'p> {
+impl<'p> ParseTarget<'p> for WithDefault<'p> {
     type Argument = ArgumentV1<'p>;
 
     fn validate_name(&mut self, name: &str) -> Option<usize> {
-        self.0.iter().position(|p| p.name.map_or(false, |n| n == name))
+        if let Some(idx) = self.params.validate_name(name) {
+            return Some(idx)
+        }
+        let k = match self.missing.iter().position(|n| n == name) {
+            Some(k) => k,
+            None => {
+                self.missing.push(Cow::Owned(name.into()));
+                self.missing.len() - 1
+            }
+        };
+        Some(usize::MAX - k)
     }
 
     fn validate_index(&mut self, index: usize) -> bool {
-        index < self.0.len()
+        self.params.validate_index(index)
+    }
+
+    fn resolve_index<'s>(&mut self, index: usize) -> Result<usize, Error<'s>> {
+        self.params.resolve_index(index)
     }
 
     fn format<'s>(&mut self, spec: &'s str, idx: usize) -> Result<Self::Argument, Error<'s>> {
-        self.0[idx].value.by_name(spec, idx)
+        self.param(idx)?.value().by_name(spec, idx)
+    }
+
+    fn format_usize(&mut self, idx: usize) -> Result<Self::Argument, CountError> {
+        // a missing parameter is no more use as a count than a string is
+        match self.param(idx).map(|param| &param.as_usize) {
+            Ok(&Ok(ref value)) => Ok(ArgumentV1::from_usize(value)),
+            Ok(&Err(reason)) => Err(reason),
+            Err(_) => Err(CountError::WrongType),
+        }
+    }
+
+    fn count(&mut self, idx: usize) -> Option<usize> {
+        self.param(idx).ok()?.as_usize.ok()
+    }
+
+    fn type_name(&mut self, idx: usize) -> Option<&'static str> {
+        self.param(idx).ok().map(|param| param.value().type_name())
+    }
+}
+
+// Checks against parameters which the result will own, so no arguments are
+// kept; `OwnedFormatBuf` builds them anew from its slots.
+struct OwnedParse<'a>(Vec<Param<'a>>);
+
+impl<'p, 'a> ParseTarget<'p> for OwnedParse<'a> {
+    type Argument = ();
+
+    fn validate_name(&mut self, name: &str) -> Option<usize> {
+        ImmediateParse(&self.0).validate_name(name)
+    }
+
+    fn validate_index(&mut self, index: usize) -> bool {
+        ImmediateParse(&self.0).validate_index(index)
+    }
+
+    fn resolve_index<'s>(&mut self, index: usize) -> Result<usize, Error<'s>> {
+        ImmediateParse(&self.0).resolve_index(index)
+    }
+
+    fn format<'s>(&mut self, spec: &'s str, idx: usize) -> Result<(), Error<'s>> {
+        ImmediateParse(&self.0).format(spec, idx).map(|_| ())
     }
 
-    fn format_usize(&mut self, idx: usize) -> Option<Self::Argument> {
-        self.0[idx].as_usize.as_ref().map(ArgumentV1::from_usize)
+    fn format_usize(&mut self, idx: usize) -> Result<(), CountError> {
+        ImmediateParse(&self.0).format_usize(idx).map(|_| ())
+    }
+
+    fn count(&mut self, idx: usize) -> Option<usize> {
+        ImmediateParse(&self.0).count(idx)
+    }
+
+    fn type_name(&mut self, idx: usize) -> Option<&'static str> {
+        ImmediateParse(&self.0).type_name(idx)
     }
 }
 
@@ -352,18 +2211,104 @@ impl<'p, T: FormatArgs> ParseTarget<'p> for DelayedParse<T> {
     }
 
     fn format<'s>(&mut self, spec: &'s str, idx: usize) -> Result<Self::Argument, Error<'s>> {
-        erase::codegen_get_child::<T>(spec, idx).map(PreparedArgument::Normal)
+        PreparedArgument::format(spec, idx)
+    }
+
+    fn format_usize(&mut self, idx: usize) -> Result<Self::Argument, CountError> {
+        PreparedArgument::format_usize(idx)
+    }
+
+    fn count(&mut self, _: usize) -> Option<usize> {
+        None
+    }
+}
+
+struct ValidateOnly<T>(DelayedParse<T>);
+
+impl<'p, T: FormatArgs> ParseTarget<'p> for ValidateOnly<T> {
+    type Argument = ();
+
+    fn validate_name(&mut self, name: &str) -> Option<usize> {
+        T::validate_name(name)
+    }
+
+    fn validate_index(&mut self, index: usize) -> bool {
+        T::validate_index(index)
+    }
+
+    fn format<'s>(&mut self, spec: &'s str, idx: usize) -> Result<(), Error<'s>> {
+        self.0.format(spec, idx).map(|_| ())
+    }
+
+    fn format_usize(&mut self, idx: usize) -> Result<(), CountError> {
+        self.0.format_usize(idx).map(|_| ())
+    }
+
+    fn count(&mut self, idx: usize) -> Option<usize> {
+        self.0.count(idx)
+    }
+}
+
+// Finds which parameters are used, for `Params::lazy`. Only names and
+// indices are checked; the rest waits until the values are at hand.
+struct LazyParse<'n> {
+    names: &'n [&'n str],
+    used: Vec<bool>,
+}
+
+impl<'p, 'n> ParseTarget<'p> for LazyParse<'n> {
+    type Argument = ();
+
+    fn validate_name(&mut self, name: &str) -> Option<usize> {
+        let idx = self.names.iter().position(|&n| !n.is_empty() && n == name)?;
+        self.used[idx] = true;
+        Some(idx)
     }
 
-    fn format_usize(&mut self, idx: usize) -> Option<Self::Argument> {
-        T::as_usize(idx).map(PreparedArgument::Usize)
+    fn validate_index(&mut self, index: usize) -> bool {
+        match self.used.get_mut(index) {
+            Some(used) => *used = true,
+            None => return false,
+        }
+        true
+    }
+
+    fn resolve_index<'s>(&mut self, index: usize) -> Result<usize, Error<'s>> {
+        if self.validate_index(index) {
+            Ok(index)
+        } else {
+            Err(Error::MissingArgument { index: index, supplied: self.used.len() })
+        }
+    }
+
+    fn format<'s>(&mut self, _: &'s str, _: usize) -> Result<(), Error<'s>> {
+        Ok(())
+    }
+
+    fn format_usize(&mut self, _: usize) -> Result<(), CountError> {
+        Ok(())
+    }
+
+    fn count(&mut self, _: usize) -> Option<usize> {
+        None
     }
 }
 
 struct Parsed<'s, P: ParseTarget<'s>> {
+    // The format string, with any later additions.
+    spec: Cow<'s, str>,
     pieces: Vec<Cow<'s, str>>,
+    // The same as `pieces` when none of them are owned, to save collecting
+    // them anew every time the arguments are built.
+    borrowed: Option<Vec<&'s str>>,
     args: Vec<P::Argument>,
+    // Which argument and format type (or `None` for a count) each entry of
+    // `args` stands for, so repeats can share one entry.
+    slots: Vec<(usize, Option<Cow<'s, str>>)>,
     fmt: Option<Vec<v1::Argument>>,
+    // The names and indices of all referenced arguments.
+    names: Vec<Cow<'s, str>>,
+    indices: Vec<usize>,
 }
 
 impl<'s, P: ParseTarget<'s>> Clone for Parsed<'s, P>
@@ -371,46 +2316,309 @@ impl<'s, P: ParseTarget<'s>> Clone for Parsed<'s, P>
 {
     fn clone(&self) -> Self {
         Parsed {
+            spec: self.spec.clone(),
             pieces: self.pieces.clone(),
+            borrowed: self.borrowed.clone(),
             args: self.args.clone(),
+            slots: self.slots.clone(),
             fmt: self.fmt.clone(),
+            names: self.names.clone(),
+            indices: self.indices.clone(),
         }
     }
 
     fn clone_from(&mut self, source: &Self) {
+        self.spec.clone_from(&source.spec);
         self.pieces.clone_from(&source.pieces);
+        self.borrowed.clone_from(&source.borrowed);
         self.args.clone_from(&source.args);
+        self.slots.clone_from(&source.slots);
         self.fmt.clone_from(&source.fmt);
+        self.names.clone_from(&source.names);
+        self.indices.clone_from(&source.indices);
     }
 }
 
 impl<'s, P: ParseTarget<'s>> Parsed<'s, P> {
+    fn new() -> Self {
+        Parsed {
+            spec: Cow::Borrowed(""),
+            pieces: Vec::new(),
+            borrowed: None,
+            args: Vec::new(),
+            slots: Vec::new(),
+            fmt: None,
+            names: Vec::new(),
+            indices: Vec::new(),
+        }
+    }
+
+    // Empty this of everything parsed, keeping the space allocated for it.
+    fn clear(&mut self) {
+        self.spec = Cow::Borrowed("");
+        self.pieces.clear();
+        if let Some(ref mut borrowed) = self.borrowed {
+            borrowed.clear();
+        }
+        self.args.clear();
+        self.slots.clear();
+        if let Some(ref mut fmt) = self.fmt {
+            fmt.clear();
+        }
+        self.names.clear();
+        self.indices.clear();
+    }
+
     fn newln(&mut self) {
+        self.append("\n".into());
+    }
+
+    fn append(&mut self, text: Cow<'s, str>) {
+        if text.is_empty() {
+            return
+        }
         // If fmt is None, the number of implicit formatting specifiers
         // is the same as the number of arguments.
         let len = self.fmt.as_ref().map_or(self.args.len(), |fmt| fmt.len());
+        let spec = self.spec.to_mut();
+        for c in text.chars() {
+            match c {
+                '{' => spec.push_str("{{"),
+                '}' => spec.push_str("}}"),
+                c => spec.push(c),
+            }
+        }
         if self.pieces.len() > len {
             // The final piece is after the final formatting specifier, so
             // it's okay to just add to the end of it.
-            self.pieces.last_mut().unwrap().to_mut().push_str("\n")
+            self.pieces.last_mut().unwrap().to_mut().push_str(&text);
+            self.borrowed = None;
         } else {
             // The final piece is before the final formatting specifier, so
             // a new piece needs to be added at the end.
-            self.pieces.push("\n".into())
+            match (&mut self.borrowed, &text) {
+                (&mut Some(ref mut borrowed), &Cow::Borrowed(text)) => borrowed.push(text),
+                (borrowed, _) => *borrowed = None,
+            }
+            self.pieces.push(text);
+        }
+    }
+
+    fn concat(&mut self, other: Self) {
+        // The number of formatting specifiers in each, as in `newln`.
+        let len = self.fmt.as_ref().map_or(self.args.len(), |fmt| fmt.len());
+        let other_len = other.fmt.as_ref().map_or(other.args.len(), |fmt| fmt.len());
+        let offset = self.args.len();
+        self.spec.to_mut().push_str(&other.spec);
+
+        // Unless both are implicit, make both explicit and move the other's
+        // positions past this one's arguments.
+        if self.fmt.is_some() || other.fmt.is_some() {
+            let implicit = |len| (0..len).map(|i| v1::Argument {
+                position: i,
+                format: DEFAULT_VALUE,
+            }).collect::<Vec<_>>();
+            let rebase = |count| match count {
+                v1::Count::Param(i) => v1::Count::Param(i + offset),
+                count => count,
+            };
+            let mut fmt = self.fmt.take().unwrap_or_else(|| implicit(len));
+            fmt.extend(other.fmt.unwrap_or_else(|| implicit(other_len)).into_iter().map(|mut arg| {
+                arg.position += offset;
+                arg.format.precision = rebase(arg.format.precision);
+                arg.format.width = rebase(arg.format.width);
+                arg
+            }));
+            self.fmt = Some(fmt);
+        }
+        for name in &other.names {
+            if !self.names.contains(name) {
+                self.names.push(name.clone());
+            }
+        }
+        let mut other_slots = other.slots;
+        let mut other_indices = other.indices;
+        move_defaulted(&mut other_slots, &mut other_indices, &other.names, &self.names);
+        self.args.extend(other.args);
+        self.slots.extend(other_slots);
+
+        // If this ends in text, the other's first piece continues it.
+        let mut pieces = other.pieces.into_iter();
+        if self.pieces.len() > len {
+            if let Some(first) = pieces.next() {
+                self.pieces.last_mut().unwrap().to_mut().push_str(&first);
+            }
+        }
+        self.pieces.extend(pieces);
+        self.update_borrowed();
+
+        for idx in other_indices {
+            if let Err(pos) = self.indices.binary_search(&idx) {
+                self.indices.insert(pos, idx);
+            }
+        }
+    }
+
+    // The same, as if parsed by another target with the same arguments.
+    fn retarget<Q: ParseTarget<'s, Argument = P::Argument>>(self) -> Parsed<'s, Q> {
+        Parsed {
+            spec: self.spec,
+            pieces: self.pieces,
+            borrowed: self.borrowed,
+            args: self.args,
+            slots: self.slots,
+            fmt: self.fmt,
+            names: self.names,
+            indices: self.indices,
+        }
+    }
+
+    fn is_fully_borrowed(&self) -> bool {
+        self.pieces.iter().all(|piece| match *piece {
+            Cow::Borrowed(_) => true,
+            Cow::Owned(_) => false,
+        })
+    }
+
+    fn update_borrowed(&mut self) {
+        let mut borrowed = self.borrowed.take().unwrap_or_default();
+        borrowed.clear();
+        for piece in &self.pieces {
+            match *piece {
+                Cow::Borrowed(piece) => borrowed.push(piece),
+                Cow::Owned(_) => return,
+            }
         }
+        self.borrowed = Some(borrowed);
     }
 
     #[inline]
-    fn pieces(&self) -> Vec<&str> {
-        self.pieces.iter().map(|r| &**r).collect()
+    fn with_pieces<F: FnOnce(&[&str]) -> R, R>(&self, f: F) -> R {
+        match self.borrowed {
+            Some(ref borrowed) => f(borrowed),
+            None => with_small(self.pieces.len(), "", |i| &*self.pieces[i], |p| f(p)),
+        }
+    }
+
+    // Call `f` with `Arguments` of these pieces and the given arguments,
+    // which stand for the slots, with any sections already stood in for.
+    fn with_arguments<F: FnOnce(Arguments) -> R, R>(&self, args: &[ArgumentV1], f: F) -> R {
+        self.with_pieces(|pieces| f(match self.fmt {
+            Some(ref fmt) => Arguments::new_v1_formatted(pieces, args, fmt),
+            None => Arguments::new_v1(pieces, args),
+        }))
+    }
+}
+
+// The longest list `with_small` keeps on the stack.
+const SMALL_LEN: usize = 8;
+
+// Stands in for the arguments of a list before they are filled in.
+#[inline]
+fn placeholder() -> ArgumentV1<'static> {
+    static ZERO: usize = 0;
+    ArgumentV1::from_usize(&ZERO)
+}
+
+// Call `f` with a list of `len` items made by `make`, which is kept on the
+// stack rather than allocated when it is short.
+#[inline]
+fn with_small<T, M, F, R>(len: usize, placeholder: T, mut make: M, f: F) -> R
+    where T: Copy, M: FnMut(usize) -> T, F: FnOnce(&mut [T]) -> R
+{
+    if len <= SMALL_LEN {
+        let mut stack = [placeholder; SMALL_LEN];
+        for (i, item) in stack[..len].iter_mut().enumerate() {
+            *item = make(i);
+        }
+        f(&mut stack[..len])
+    } else {
+        f(&mut (0..len).map(make).collect::<Vec<_>>())
+    }
+}
+
+fn parse<'s, P: ParseTarget<'s>>(spec: &'s str, target: &mut P, limits: &Limits)
+    -> Result<Parsed<'s, P>, Error<'s>>
+{
+    parse_directed(spec, target, limits, Direction::default())
+}
+
+fn parse_directed<'s, P>(spec: &'s str, target: &mut P, limits: &Limits, direction: Direction)
+    -> Result<Parsed<'s, P>, Error<'s>>
+    where P: ParseTarget<'s>
+{
+    parse_collecting(spec, target, false, limits, direction, BRACES)
+        .map_err(|mut errors| errors.remove(0))
+}
+
+// The usual delimiters of an argument.
+const BRACES: (char, char) = ('{', '}');
+
+fn parse_collecting<'s, P>(spec: &'s str,
+                           target: &mut P,
+                           collect: bool,
+                           limits: &Limits,
+                           direction: Direction,
+                           delims: (char, char))
+    -> Result<Parsed<'s, P>, Vec<Error<'s>>>
+    where P: ParseTarget<'s>
+{
+    if delims == BRACES {
+        if let Some(ty) = single_argument(spec) {
+            return parse_single(spec, ty, target).map_err(|e| vec![e])
+        }
+    }
+
+    let mut parser = fmt_macros::Parser::with_delims(spec, delims.0, delims.1);
+    let result = inner_parse(&mut parser, target, collect, limits, direction, Parsed::new())
+        .map(|parsed| Parsed { spec: spec.into(), ..parsed });
+    // Perform a separate check so that syntax errors take priority.
+    if parser.errors.is_empty() {
+        result
+    } else {
+        Err(vec![Error::BadSyntax(parser.errors)])
+    }
+}
+
+// The format type of a format string which is a single argument formatted
+// plainly, as the most common of all are, or `None` for any other.
+fn single_argument(spec: &str) -> Option<&'static str> {
+    match spec {
+        "{}" => Some(""),
+        "{:?}" => Some("?"),
+        _ => None,
     }
 }
 
-fn parse<'s, P: ParseTarget<'s>>(spec: &'s str, target: &mut P)
+// Build what parsing a format string of a single argument would, without
+// running the parser.
+fn parse_single<'s, P>(spec: &'s str, ty: &'static str, target: &mut P)
     -> Result<Parsed<'s, P>, Error<'s>>
+    where P: ParseTarget<'s>
+{
+    let idx = target.resolve_index(0)?;
+    let arg = target.format(ty, idx)?;
+    let mut parsed = Parsed::new();
+    parsed.spec = spec.into();
+    parsed.pieces.push("".into());
+    parsed.args.push(arg);
+    parsed.slots.push((idx, Some(ty.into())));
+    parsed.indices.push(idx);
+    parsed.update_borrowed();
+    Ok(parsed)
+}
+
+// As `parse`, but filling the storage of an emptied `Parsed` rather than
+// allocating anew.
+fn parse_reusing<'s, P>(spec: &'s str, target: &mut P, parsed: Parsed<'s, P>)
+    -> Result<Parsed<'s, P>, Error<'s>>
+    where P: ParseTarget<'s>
 {
     let mut parser = fmt_macros::Parser::new(spec);
-    let result = inner_parse(&mut parser, target);
+    let limits = Limits::default();
+    let result = inner_parse(&mut parser, target, false, &limits, Direction::default(), parsed)
+        .map(|parsed| Parsed { spec: spec.into(), ..parsed })
+        .map_err(|mut errors| errors.remove(0));
     // Perform a separate check so that syntax errors take priority.
     if parser.errors.is_empty() {
         result
@@ -419,144 +2627,294 @@ fn parse<'s, P: ParseTarget<'s>>(spec: &'s str, target: &mut P)
     }
 }
 
-fn inner_parse<'s, P>(parser: &mut fmt_macros::Parser<'s>, target: &mut P)
-    -> Result<Parsed<'s, P>, Error<'s>>
+fn inner_parse<'s, P>(parser: &mut fmt_macros::Parser<'s>,
+                      target: &mut P,
+                      collect: bool,
+                      limits: &Limits,
+                      direction: Direction,
+                      mut parsed: Parsed<'s, P>)
+    -> Result<Parsed<'s, P>, Vec<Error<'s>>>
     where P: ParseTarget<'s>
 {
     use fmt_macros as p;
 
-    const DEFAULT_FILL: char = ' ';
-    const DEFAULT_KEY: p::FormatSpec = p::FormatSpec {
-        fill: None,
-        align: p::AlignUnknown,
-        flags: 0,
-        precision: p::CountImplied,
-        width: p::CountImplied,
-        ty: "",
-    };
-    const DEFAULT_VALUE: v1::FormatSpec = v1::FormatSpec {
-        fill: DEFAULT_FILL,
-        align: v1::Alignment::Unknown,
-        flags: 0,
-        precision: v1::Count::Implied,
-        width: v1::Count::Implied,
-    };
-
-    let mut pieces = Vec::new();
-    let mut args = Vec::new();
-    let mut fmt = None;
+    // Any list of specs already allocated is kept for if one is needed.
+    let mut spare = parsed.fmt.take().unwrap_or_default();
     let mut fmt_len = 0;
+    let mut errors = Vec::new();
 
     let mut str_accum: Cow<str> = "".into();
     while let Some(piece) = parser.next() {
         match piece {
             p::Piece::String(text) => {
-                // append string to accumulator
+                // append string to accumulator; the parser hands over
+                // escaped braces already unescaped, as pieces of their own,
+                // so only text spanning an escape needs to be copied
                 if str_accum.is_empty() {
                     str_accum = text.into();
                 } else if !text.is_empty() {
                     str_accum.to_mut().push_str(text);
                 }
             }
-            p::Piece::NextArgument(arg) => {
-                let mut push_arg = |arg| {
-                    // TODO: if this arg already appears in `args`, don't push
-                    // it another time, reuse the previous index.
-                    let len = args.len();
-                    args.push(arg);
-                    len
-                };
+            p::Piece::NextArgument(mut arg) => {
+                // alignment is to the start or end of the text, which are
+                // the other way around for text running right to left
+                if direction == Direction::RightToLeft {
+                    arg.format.align = match arg.format.align {
+                        p::AlignLeft => p::AlignRight,
+                        p::AlignRight => p::AlignLeft,
+                        align => align,
+                    };
+                }
 
                 // flush accumulator always
-                pieces.push(std::mem::replace(&mut str_accum, "".into()));
-
-                // convert the argument
-                let idx = match arg.position {
-                    p::Position::ArgumentIs(idx) => {
-                        if !target.validate_index(idx) {
-                            return Err(Error::BadIndex(idx))
-                        }
-                        idx
+                parsed.pieces.push(::core::mem::replace(&mut str_accum, "".into()));
+
+                // convert the argument, stopping at the first error unless
+                // all errors are wanted
+                let converted = convert_argument(target, arg, &mut parsed, &mut spare, fmt_len,
+                                                 limits);
+                if let Err(e) = converted {
+                    errors.push(e);
+                    if !collect {
+                        break
                     }
-                    p::Position::ArgumentNamed(name) => {
-                        match target.validate_name(name) {
-                            Some(idx) => idx,
-                            None => return Err(Error::BadName(name))
-                        }
-                    }
-                };
-                let argument_pos = push_arg(target.format(arg.format.ty, idx)?);
-
-                // convert the format spec
-                let mut convert_count = |c| -> Result<v1::Count, Error<'s>> {
-                    Ok(match c {
-                        p::CountIs(val) => v1::Count::Is(val),
-                        p::CountIsName(name) => {
-                            let idx = match target.validate_name(name) {
-                                Some(idx) => idx,
-                                None => return Err(Error::BadName(name))
-                            };
-                            v1::Count::Param(push_arg(match target.format_usize(idx) {
-                                Some(arg) => arg,
-                                None => return Err(Error::BadCount(idx))
-                            }))
-                        }
-                        p::CountIsParam(idx) => {
-                            if !target.validate_index(idx) {
-                                return Err(Error::BadIndex(idx))
-                            }
-                            v1::Count::Param(push_arg(match target.format_usize(idx) {
-                                Some(arg) => arg,
-                                None => return Err(Error::BadCount(idx))
-                            }))
-                        },
-                        p::CountImplied => v1::Count::Implied,
-                    })
-                };
-
-                // If specs were implicit but this is non-default, fill in the
-                // previously-implicit values.
-                if fmt.is_none() && (arg.format != DEFAULT_KEY || argument_pos != fmt_len) {
-                    fmt = Some((0..fmt_len).map(|i| v1::Argument {
-                        position: i,
-                        format: DEFAULT_VALUE,
-                    }).collect::<Vec<_>>());
-                }
-
-                // If specs are currently explicit, push this spec.
-                if let Some(fmt) = fmt.as_mut() {
-                    let spec = v1::FormatSpec {
-                        fill: arg.format.fill.unwrap_or(DEFAULT_FILL),
-                        flags: arg.format.flags,
-                        align: match arg.format.align {
-                            p::AlignLeft => v1::Alignment::Left,
-                            p::AlignRight => v1::Alignment::Right,
-                            p::AlignCenter => v1::Alignment::Center,
-                            p::AlignUnknown => v1::Alignment::Unknown,
-                        },
-                        precision: convert_count(arg.format.precision)?,
-                        width: convert_count(arg.format.width)?,
-                    };
-
-                    // push the format spec and argument value
-                    fmt.push(v1::Argument {
-                        position: argument_pos,
-                        format: spec,
-                    })
                 }
 
                 fmt_len += 1;
             }
         }
     }
+    if !errors.is_empty() {
+        return Err(errors)
+    }
     // flush accumulator if needed
     if !str_accum.is_empty() {
-        pieces.push(str_accum);
+        parsed.pieces.push(str_accum);
+    }
+
+    parsed.update_borrowed();
+    Ok(parsed)
+}
+
+const DEFAULT_FILL: char = ' ';
+const DEFAULT_VALUE: v1::FormatSpec = v1::FormatSpec {
+    fill: DEFAULT_FILL,
+    align: v1::Alignment::Unknown,
+    flags: 0,
+    precision: v1::Count::Implied,
+    width: v1::Count::Implied,
+};
+
+fn resolve<'s, P>(target: &mut P, position: fmt_macros::Position<'s>) -> Result<usize, Error<'s>>
+    where P: ParseTarget<'s>
+{
+    match position {
+        fmt_macros::ArgumentIs(idx) => target.resolve_index(idx),
+        fmt_macros::ArgumentNamed(name) => target.resolve_name(name),
+    }
+}
+
+fn check_limit<'s>(value: usize, limit: usize) -> Result<usize, Error<'s>> {
+    if value > limit {
+        Err(Error::CountTooLarge { value: value, limit: limit })
+    } else {
+        Ok(value)
+    }
+}
+
+fn position_name<'s>(position: fmt_macros::Position<'s>) -> Option<&'s str> {
+    match position {
+        fmt_macros::ArgumentIs(_) => None,
+        fmt_macros::ArgumentNamed(name) => Some(name),
+    }
+}
+
+// The name which `new_with_default` gave the default to at `idx`, from those
+// its indices count down through.
+#[inline]
+fn defaulted_name<'n, 's>(names: &'n [Cow<'s, str>], idx: usize) -> Option<&'n Cow<'s, str>> {
+    names.get(usize::MAX - idx)
+}
+
+// Give the arguments which `new_with_default` gave the default to indices
+// counting down through the names `to` instead of `from`.
+fn move_defaulted<'s>(slots: &mut [(usize, Option<Cow<'s, str>>)],
+                      indices: &mut Vec<usize>,
+                      from: &[Cow<'s, str>],
+                      to: &[Cow<'s, str>]) {
+    let moved = |idx: usize| match defaulted_name(from, idx) {
+        Some(name) => to.iter().position(|n| n == name).map_or(idx, |k| usize::MAX - k),
+        None => idx,
+    };
+    for slot in slots {
+        slot.0 = moved(slot.0);
+    }
+    for idx in indices.iter_mut() {
+        *idx = moved(*idx);
+    }
+    indices.sort();
+}
+
+// Note that the argument at `idx` is used, by `name` if it was named.
+fn record_reference<'s>(names: &mut Vec<Cow<'s, str>>,
+                        indices: &mut Vec<usize>,
+                        name: Option<&'s str>,
+                        idx: usize) {
+    if let Some(name) = name {
+        if !names.iter().any(|n| n == name) {
+            names.push(name.into());
+        }
+    }
+    if let Err(pos) = indices.binary_search(&idx) {
+        indices.insert(pos, idx);
+    }
+}
+
+fn convert_argument<'s, P>(target: &mut P,
+                           arg: fmt_macros::Argument<'s>,
+                           parsed: &mut Parsed<'s, P>,
+                           spare: &mut Vec<v1::Argument>,
+                           fmt_len: usize,
+                           limits: &Limits)
+    -> Result<(), Error<'s>>
+    where P: ParseTarget<'s>
+{
+    use fmt_macros as p;
+
+    const DEFAULT_KEY: p::FormatSpec = p::FormatSpec {
+        fill: None,
+        align: p::AlignUnknown,
+        flags: 0,
+        precision: p::CountImplied,
+        width: p::CountImplied,
+        ty: "",
+    };
+
+    let Parsed {
+        ref mut args, ref mut slots, ref mut fmt, ref mut names, ref mut indices, ..
+    } = *parsed;
+    let mut reference = |name, idx| record_reference(names, indices, name, idx);
+    let mut push_arg = |idx, ty: Option<Cow<'s, str>>, arg| {
+        let found = slots.iter().position(|s| s.0 == idx && s.1 == ty);
+        if let Some(pos) = found {
+            return pos
+        }
+        slots.push((idx, ty));
+        args.push(arg);
+        args.len() - 1
+    };
+
+    // convert the argument, which for a conditional section is the value
+    // it writes if that is not blank, and for a choice the integer it is
+    // selected by
+    if let Some(choices) = choice::parse(arg.format.ty) {
+        choices?;
+    }
+    let idx = resolve(target, arg.position)?;
+    reference(position_name(arg.position), idx);
+    let (ty, argument) = match arg.prefix {
+        Some(prefix) => (section::conditional(prefix).into(), target.format("", idx)?),
+        None => (arg.format.ty.into(), target.format(arg.format.ty, idx)?),
+    };
+    let argument_pos = push_arg(idx, Some(ty), argument);
+
+    // convert the format spec
+    let mut convert_count = |c, limit| -> Result<v1::Count, Error<'s>> {
+        let (name, idx) = match c {
+            p::CountIs(val) => return check_limit(val, limit).map(v1::Count::Is),
+            p::CountIsName(name) => (Some(name), resolve(target, p::Position::ArgumentNamed(name))?),
+            p::CountIsParam(idx) => (None, resolve(target, p::Position::ArgumentIs(idx))?),
+            p::CountImplied => return Ok(v1::Count::Implied),
+        };
+        reference(name, idx);
+        let arg = target.format_usize(idx).map_err(|reason| Error::BadCount {
+            idx: idx,
+            ty: target.type_name(idx),
+            reason: reason,
+        })?;
+        if let Some(value) = target.count(idx) {
+            check_limit(value, limit)?;
+        }
+        Ok(v1::Count::Param(push_arg(idx, None, arg)))
+    };
+
+    // If specs were implicit but this is non-default, fill in the
+    // previously-implicit values. The trait is already chosen by the
+    // argument itself, so `ty` doesn't count against being default.
+    let is_default = p::FormatSpec { ty: "", ..arg.format } == DEFAULT_KEY;
+    if fmt.is_none() && (!is_default || argument_pos != fmt_len) {
+        let mut filled = ::core::mem::replace(spare, Vec::new());
+        filled.extend((0..fmt_len).map(|i| v1::Argument {
+            position: i,
+            format: DEFAULT_VALUE,
+        }));
+        *fmt = Some(filled);
+    }
+
+    // If specs are currently explicit, push this spec.
+    if let Some(fmt) = fmt.as_mut() {
+        let spec = v1::FormatSpec {
+            fill: arg.format.fill.unwrap_or(DEFAULT_FILL),
+            flags: arg.format.flags,
+            align: match arg.format.align {
+                p::AlignLeft => v1::Alignment::Left,
+                p::AlignRight => v1::Alignment::Right,
+                p::AlignCenter => v1::Alignment::Center,
+                p::AlignUnknown => v1::Alignment::Unknown,
+            },
+            precision: convert_count(arg.format.precision, limits.max_precision)?,
+            width: convert_count(arg.format.width, limits.max_width)?,
+        };
+
+        // push the format spec and argument value
+        fmt.push(v1::Argument {
+            position: argument_pos,
+            format: spec,
+        })
     }
 
-    Ok(Parsed {
-        pieces: pieces,
-        args: args,
-        fmt: fmt,
-    })
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn has_fmt(spec: &str) -> bool {
+        let params = [Param::normal(&1), Param::normal(&2)];
+        parse(spec, &mut ImmediateParse(&params), &Limits::default()).unwrap().fmt.is_some()
+    }
+
+    #[test]
+    fn implicit_specs() {
+        assert!(!has_fmt(""));
+        assert!(!has_fmt("{} and {}"));
+        assert!(!has_fmt("{0} {1}"));
+        assert!(!has_fmt("{1} {0:?} {:x}"));
+        assert!(has_fmt("{0} {0}"));
+        assert!(has_fmt("{} {:>5}"));
+        assert!(has_fmt("{:.1$}"));
+    }
+
+    #[test]
+    fn borrowed_pieces() {
+        let params = [Param::normal(&1)];
+        let borrowed = |spec| {
+            let parsed = parse(spec, &mut ImmediateParse(&params), &Limits::default()).unwrap();
+            let borrowed = parsed.pieces.iter().map(|p| match *p {
+                Cow::Borrowed(_) => true,
+                Cow::Owned(_) => false,
+            }).collect::<Vec<_>>();
+            assert_eq!(parsed.borrowed.is_some(), borrowed.iter().all(|&b| b));
+            borrowed
+        };
+        assert_eq!(borrowed("a {} b"), [true, true]);
+        assert_eq!(borrowed("{}"), [true]);
+        assert_eq!(borrowed("{{"), [true]);
+        // only text joined across an escape needs copying
+        assert_eq!(borrowed("{{}}"), [false]);
+        assert_eq!(borrowed("a {{ {} b"), [false, true]);
+        assert_eq!(borrowed("a {} }} b"), [true, false]);
+    }
 }
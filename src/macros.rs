@@ -11,25 +11,47 @@
 ///
 /// The syntax accepted is the same as `format_args!`. See the module-level
 /// docs for more detail.
+///
+/// Because the format string is not known until runtime, variables in scope
+/// are not captured by name as `format!` captures them. They may instead be
+/// listed after a semicolon, so that `rt_format_args!(spec, 1; x, y)` is
+/// short for `rt_format_args!(spec, 1, x = x, y = y)`. Arguments given
+/// before the semicolon take precedence over listed variables of the same
+/// name, and listed variables the format string does not use are ignored.
 #[macro_export]
 macro_rules! rt_format_args {
-    (@[$spec:expr] [$($args:tt)*] $name:tt = $e:expr, $($rest:tt)*) => {
-        rt_format_args!(@[$spec] [$($args)* $crate::Param::named(stringify!($name), &$e),] $($rest)*)
+    (@[$new:path, $spec:expr] [$($args:tt)*] ; $($name:ident),* $(,)*) => {
+        rt_format_args!(@[$new, $spec]
+                        [$($args)* $($crate::Param::named(stringify!($name), &$name),)*])
+    };
+    (@[$new:path, $spec:expr] [$($args:tt)*] $name:tt = $e:expr; $($rest:tt)*) => {
+        rt_format_args!(@[$new, $spec]
+                        [$($args)* $crate::Param::named(stringify!($name), &$e),]; $($rest)*)
+    };
+    (@[$new:path, $spec:expr] [$($args:tt)*] $name:tt = $e:expr, $($rest:tt)*) => {
+        rt_format_args!(@[$new, $spec]
+                        [$($args)* $crate::Param::named(stringify!($name), &$e),] $($rest)*)
     };
-    (@[$spec:expr] [$($args:tt)*] $name:tt = $e:expr) => {
-        rt_format_args!(@[$spec] [$($args)* $crate::Param::named(stringify!($name), &$e),])
+    (@[$new:path, $spec:expr] [$($args:tt)*] $name:tt = $e:expr) => {
+        rt_format_args!(@[$new, $spec] [$($args)* $crate::Param::named(stringify!($name), &$e),])
     };
-    (@[$spec:expr] [$($args:tt)*] $e:expr, $($rest:tt)*) => {
-        rt_format_args!(@[$spec] [$($args)* $crate::Param::normal(&$e),] $($rest)*)
+    (@[$new:path, $spec:expr] [$($args:tt)*] $e:expr; $($rest:tt)*) => {
+        rt_format_args!(@[$new, $spec] [$($args)* $crate::Param::normal(&$e),]; $($rest)*)
     };
-    (@[$spec:expr] [$($args:tt)*] $e:expr) => {
-        rt_format_args!(@[$spec] [$($args)* $crate::Param::normal(&$e),])
+    (@[$new:path, $spec:expr] [$($args:tt)*] $e:expr, $($rest:tt)*) => {
+        rt_format_args!(@[$new, $spec] [$($args)* $crate::Param::normal(&$e),] $($rest)*)
     };
-    (@[$spec:expr] [$($args:tt)*]) => {
-        $crate::FormatBuf::new(&$spec, &[$($args)*])
+    (@[$new:path, $spec:expr] [$($args:tt)*] $e:expr) => {
+        rt_format_args!(@[$new, $spec] [$($args)* $crate::Param::normal(&$e),])
+    };
+    (@[$new:path, $spec:expr] [$($args:tt)*]) => {
+        $new(&$spec, &[$($args)*])
     };
     ($spec:expr, $($rest:tt)*) => {
-        rt_format_args!(@[$spec] [] $($rest)*)
+        rt_format_args!(@[$crate::FormatBuf::new, $spec] [] $($rest)*)
+    };
+    ($spec:expr; $($rest:tt)*) => {
+        rt_format_args!(@[$crate::FormatBuf::new, $spec] []; $($rest)*)
     };
     ($spec:expr) => {
         $crate::FormatBuf::new(&$spec, &[])
@@ -46,15 +68,61 @@ macro_rules! rt_format_args {
 #[macro_export]
 macro_rules! rt_format {
     ($($rest:tt)*) => {
-        rt_format_args!($($rest)*).map(|x| x.with(::std::fmt::format))
+        rt_format_args!($($rest)*).map(|x| x.format())
     }
 }
 
+/// Format a `String` as `rt_format!` does, first checking at compile time
+/// that the braces of the format string are balanced.
+///
+/// The format string must be a constant expression, such as a literal.
+/// Unbalanced braces fail to compile; all other checks still happen at
+/// runtime. See `validate_braces` for the rules.
+#[macro_export]
+macro_rules! rt_format_checked {
+    (@[$spec:expr] $($all:tt)*) => {{
+        // an array of the wrong length fails to compile
+        const _BALANCED_BRACES: [(); 1] = [(); $crate::validate_braces($spec) as usize];
+        rt_format!($($all)*)
+    }};
+    ($spec:expr) => {
+        rt_format_checked!(@[$spec] $spec)
+    };
+    ($spec:expr, $($rest:tt)*) => {
+        rt_format_checked!(@[$spec] $spec, $($rest)*)
+    };
+    ($spec:expr; $($rest:tt)*) => {
+        rt_format_checked!(@[$spec] $spec; $($rest)*)
+    };
+}
+
+/// Format a `String` as `rt_format!` does, reporting any argument which the
+/// format string never uses.
+///
+/// Unused named arguments give `UnusedArgument` and unused positional ones
+/// `UnusedIndex`, as with `FormatBuf::new_exhaustive`. Variables listed after
+/// a semicolon must also be used.
+#[macro_export]
+macro_rules! rt_format_strict {
+    ($spec:expr) => {
+        $crate::FormatBuf::new_exhaustive(&$spec, &[]).map(|x| x.format())
+    };
+    ($spec:expr, $($rest:tt)*) => {
+        rt_format_args!(@[$crate::FormatBuf::new_exhaustive, $spec] [] $($rest)*)
+            .map(|x| x.format())
+    };
+    ($spec:expr; $($rest:tt)*) => {
+        rt_format_args!(@[$crate::FormatBuf::new_exhaustive, $spec] []; $($rest)*)
+            .map(|x| x.format())
+    };
+}
+
 /// Print to standard output with a runtime format string.
 ///
 /// Returns a `Result<(), Error>`. Panics if writing to stdout fails. See the
 /// module-level docs for more information.
 #[macro_export]
+#[cfg(feature = "std")]
 macro_rules! rt_print {
     ($($rest:tt)*) => {
         rt_format_args!($($rest)*).map(|x| x.with($crate::_print))
@@ -66,6 +134,7 @@ macro_rules! rt_print {
 /// Returns a `Result<(), Error>`. Panics if writing to stdout fails. See the
 /// module-level docs for more information.
 #[macro_export]
+#[cfg(feature = "std")]
 macro_rules! rt_println {
     ($($rest:tt)*) => {
         rt_format_args!($($rest)*).map(|mut x| x.newln().with($crate::_print))
@@ -84,7 +153,7 @@ macro_rules! rt_println {
 macro_rules! rt_write {
     ($dest:expr, $($rest:tt)*) => {
         rt_format_args!($($rest)*).and_then(|x|
-            x.with(|args| $dest.write_fmt(args)).map_err(::std::convert::From::from)
+            x.with(|args| $dest.write_fmt(args)).map_err(|e| x._write_error(e))
         )
     }
 }
@@ -101,7 +170,23 @@ macro_rules! rt_write {
 macro_rules! rt_writeln {
     ($dest:expr, $($rest:tt)*) => {
         rt_format_args!($($rest)*).and_then(|mut x|
-            x.newln().with(|args| $dest.write_fmt(args)).map_err(::std::convert::From::from)
+            x.newln().with(|args| $dest.write_fmt(args)).map_err(|e| x._write_error(e))
+        )
+    }
+}
+
+/// Append runtime-formatted data to a `std::fmt::Write`, such as a `String`.
+///
+/// Unlike `rt_write!`, only `fmt::Write` is accepted, so the destination
+/// may be given either by value or as a mutable reference.
+///
+/// Returns a `Result<(), Error>`. See the module-level docs for more
+/// information.
+#[macro_export]
+macro_rules! rt_format_to {
+    ($dest:expr, $($rest:tt)*) => {
+        rt_format_args!($($rest)*).and_then(|x|
+            x.write_fmt(&mut $dest).map_err(|e| x._write_error(e))
         )
     }
 }
@@ -0,0 +1,220 @@
+//! Prepared formats for maps, whose keys are only known at runtime.
+//!
+//! `FormatArgs` checks names without a value at hand, so a `HashMap` or
+//! `BTreeMap` cannot implement it. A `MapFormat` is instead prepared against
+//! the type of the map's values alone: each name the format string uses is
+//! given an index as it is parsed, and looked up in the map by that name
+//! each time it is formatted.
+//!
+//! Positions are looked up the same way, in maps whose entries have an order
+//! of their own. For a `BTreeMap`, that is the sorted order of its keys, so
+//! `{0}` is the entry with the least key, `{1}` the next, and so on. As
+//! entries come and go, the entry a position names changes with them, but
+//! the same entries always give the same output.
+//!
+//! ```rust
+//! use std::collections::HashMap;
+//! use runtime_fmt::map::MapFormat;
+//!
+//! let prepared = MapFormat::<String>::prepare("{user}@{host:>12}").unwrap();
+//! let mut config = HashMap::new();
+//! config.insert("user".to_owned(), "alice".to_owned());
+//! config.insert("host".to_owned(), "example.org".to_owned());
+//! assert_eq!(prepared.format(&config).unwrap(), "alice@ example.org");
+//!
+//! config.remove("host");
+//! assert!(prepared.format(&config).is_err());
+//! ```
+use alloc::borrow::Cow;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+use core::fmt;
+use core::marker::PhantomData;
+#[cfg(feature = "std")]
+use std::collections::HashMap;
+#[cfg(feature = "std")]
+use std::hash::{BuildHasher, Hash};
+use {section, Arguments, CountError, Error, Limits, ParseTarget, Parsed, PreparedArgument};
+use {parse, placeholder, with_small};
+
+/// Maps from names to values, which a `MapFormat` may be formatted with.
+pub trait Lookup<V> {
+    /// Return the value with the given name, if there is one.
+    fn lookup(&self, name: &str) -> Option<&V>;
+
+    /// Return the name of the entry at the given position, for maps whose
+    /// entries have an order of their own, or `None` if there is no such
+    /// entry or no such order.
+    #[inline]
+    fn nth_key(&self, _: usize) -> Option<&str> {
+        None
+    }
+}
+
+impl<K: Borrow<str> + Ord, V> Lookup<V> for BTreeMap<K, V> {
+    #[inline]
+    fn lookup(&self, name: &str) -> Option<&V> {
+        self.get(name)
+    }
+
+    #[inline]
+    fn nth_key(&self, i: usize) -> Option<&str> {
+        self.keys().nth(i).map(|k| k.borrow())
+    }
+}
+
+#[cfg(feature = "std")]
+impl<K: Borrow<str> + Hash + Eq, V, S: BuildHasher> Lookup<V> for HashMap<K, V, S> {
+    #[inline]
+    fn lookup(&self, name: &str) -> Option<&V> {
+        self.get(name)
+    }
+}
+
+/// A pre-checked format string, ready for maps with values of a specific
+/// type to be formatted against it.
+///
+/// Every value formatted must implement the traits its format types
+/// require, and one used as a width or precision must be a `usize`, which
+/// is checked when preparing. Whether the map holds each name or position
+/// can only be checked when it is formatted.
+pub struct MapFormat<'s, V> {
+    // Each value is the only element of a one-element array, which is the
+    // `FormatArgs` its formatting functions were found for.
+    inner: Parsed<'s, KeyedParse<V>>,
+    // The names and positions the format string uses, each at the index it
+    // was given.
+    keys: Vec<Key>,
+}
+
+// A name or position which a `MapFormat` looks up each time it is formatted.
+#[derive(Clone, PartialEq)]
+enum Key {
+    Name(String),
+    Position(usize),
+}
+
+impl<'s, V> MapFormat<'s, V> {
+    /// Prepare a format string against maps whose values have type `V`.
+    pub fn prepare(spec: &'s str) -> Result<Self, Error<'s>> {
+        let mut target = KeyedParse { keys: Vec::new(), value: PhantomData };
+        let inner = parse(spec, &mut target, &Limits::default())?;
+        Ok(MapFormat { inner: inner, keys: target.keys })
+    }
+
+    /// Return the format string this was prepared from, with its braces
+    /// escaped.
+    #[inline]
+    pub fn spec(&self) -> &str {
+        &self.inner.spec
+    }
+
+    /// Iterate over the names this format string refers to, in order of first
+    /// appearance, including those used as counts.
+    ///
+    /// Errors refer to each name or position by its place among those the
+    /// format string uses, in order of first appearance.
+    #[inline]
+    pub fn referenced_names(&self) -> impl Iterator<Item=&str> {
+        self.keys.iter().filter_map(|k| match *k {
+            Key::Name(ref name) => Some(&**name),
+            Key::Position(_) => None,
+        })
+    }
+
+    /// Call a function accepting `Arguments` with the values of the given
+    /// map, or fail with `BadName` if it is missing a name this format uses,
+    /// or `BadIndex` if it has no entry at a position this format uses.
+    pub fn with<'a, M, F, R>(&'a self, map: &M, f: F) -> Result<R, Error<'a>>
+        where M: Lookup<V> + ?Sized, F: FnOnce(Arguments) -> R
+    {
+        let mut values = Vec::with_capacity(self.keys.len());
+        for key in &self.keys {
+            let value = match *key {
+                Key::Name(ref name) => map.lookup(name)
+                    .ok_or_else(|| Error::BadName(Cow::Borrowed(name)))?,
+                Key::Position(i) => map.nth_key(i)
+                    .and_then(|name| map.lookup(name))
+                    .ok_or(Error::BadIndex(i))?,
+            };
+            values.push(::core::array::from_ref(value));
+        }
+        let inner = &self.inner;
+        let argument = |i: usize| inner.args[i].argument(values[inner.slots[i].0]);
+        Ok(with_small(inner.args.len(), placeholder(), argument, |args| {
+            section::resolved(args, &inner.slots, |args| inner.with_arguments(args, f))
+        }))
+    }
+
+    /// Format the given map to a `String`.
+    #[inline]
+    pub fn format<'a, M>(&'a self, map: &M) -> Result<String, Error<'a>>
+        where M: Lookup<V> + ?Sized
+    {
+        self.with(map, ::alloc::fmt::format)
+    }
+
+    /// Write the given map to a `fmt::Write`.
+    #[inline]
+    pub fn write_fmt<'a, M, W>(&'a self, map: &M, dest: &mut W) -> Result<(), Error<'a>>
+        where M: Lookup<V> + ?Sized, W: fmt::Write + ?Sized
+    {
+        self.with(map, |args| dest.write_fmt(args))?.map_err(Error::from)
+    }
+}
+
+impl<'s, V> Clone for MapFormat<'s, V> {
+    fn clone(&self) -> Self {
+        MapFormat { inner: self.inner.clone(), keys: self.keys.clone() }
+    }
+}
+
+// Gives each new name or position the next index, and checks each argument
+// against the type of the values alone.
+struct KeyedParse<V> {
+    keys: Vec<Key>,
+    value: PhantomData<fn(&V)>,
+}
+
+impl<V> KeyedParse<V> {
+    fn key(&mut self, key: Key) -> usize {
+        match self.keys.iter().position(|k| *k == key) {
+            Some(idx) => idx,
+            None => {
+                self.keys.push(key);
+                self.keys.len() - 1
+            }
+        }
+    }
+}
+
+impl<'p, V> ParseTarget<'p> for KeyedParse<V> {
+    type Argument = PreparedArgument<[V; 1]>;
+
+    fn validate_name(&mut self, name: &str) -> Option<usize> {
+        Some(self.key(Key::Name(name.into())))
+    }
+
+    // positions are only looked up once there is a map to look in
+    fn validate_index(&mut self, _: usize) -> bool {
+        true
+    }
+
+    fn resolve_index<'s>(&mut self, index: usize) -> Result<usize, Error<'s>> {
+        Ok(self.key(Key::Position(index)))
+    }
+
+    fn format<'s>(&mut self, spec: &'s str, idx: usize) -> Result<Self::Argument, Error<'s>> {
+        PreparedArgument::format(spec, 0).map_err(|e| e.reindexed(idx))
+    }
+
+    fn format_usize(&mut self, _: usize) -> Result<Self::Argument, CountError> {
+        PreparedArgument::format_usize(0)
+    }
+
+    fn count(&mut self, _: usize) -> Option<usize> {
+        None
+    }
+}
@@ -0,0 +1,88 @@
+//! Sections whose output is only decided as they are formatted: choices
+//! such as `{n:plural(1#item|other#items)}`, and conditional sections such
+//! as `{phone?Tel: }`, which are left out when their argument is blank.
+//!
+//! A section is parsed into an argument like any other, with a format type
+//! of its own, and stood in for by a wrapper each time it is formatted.
+use alloc::borrow::Cow;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use choice;
+use erase;
+use {ArgumentV1, DEFAULT_VALUE};
+
+// A conditional section is kept as the format type `??` followed by its
+// prefix, which no format string can spell, as the type `?` ends a spec.
+const CONDITIONAL: &str = "??";
+
+/// The format type with which the argument of a choice is formatted, as an
+/// `Integer`. No format string can spell it, as `#` is taken for a flag.
+pub const INTEGER: &str = "#";
+
+/// The format type kept for a conditional section with the given prefix.
+pub fn conditional(prefix: &str) -> String {
+    let mut ty = String::with_capacity(CONDITIONAL.len() + prefix.len());
+    ty.push_str(CONDITIONAL);
+    ty.push_str(prefix);
+    ty
+}
+
+/// The format type with which the value of a section is formatted, or the
+/// given format type itself if it is not that of a section.
+pub fn argument_type(ty: &str) -> &str {
+    if ty.starts_with(CONDITIONAL) {
+        ""
+    } else if let Some(Ok(_)) = choice::parse(ty) {
+        INTEGER
+    } else {
+        ty
+    }
+}
+
+fn is_section(ty: &str) -> bool {
+    argument_type(ty) != ty
+}
+
+// Stands in for the argument of a section, writing the section in its place.
+struct Section<'a, 't> {
+    arg: ArgumentV1<'a>,
+    ty: &'t str,
+}
+
+impl<'a, 't> fmt::Display for Section<'a, 't> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text = erase::render(self.arg, DEFAULT_VALUE)?;
+        if let Some(Ok(choices)) = choice::parse(self.ty) {
+            // the text is padded and truncated as a string argument would be
+            return f.pad(choices.select(text.parse().ok()))
+        }
+        let prefix = &self.ty[CONDITIONAL.len()..];
+        // a value of only whitespace is as blank as one of nothing at all
+        if text.trim().is_empty() {
+            return Ok(())
+        }
+        f.write_str(prefix)?;
+        f.write_str(&text)
+    }
+}
+
+/// Call `f` with `args`, each of which stands for the slot beside it, after
+/// standing a wrapper in for the argument of each section.
+pub fn resolved<'a, F, R>(args: &[ArgumentV1<'a>], slots: &[(usize, Option<Cow<str>>)], f: F)
+    -> R
+    where F: FnOnce(&[ArgumentV1]) -> R
+{
+    if !slots.iter().any(|slot| slot.1.as_ref().map_or(false, |ty| is_section(ty))) {
+        return f(args)
+    }
+    let sections: Vec<_> = args.iter().zip(slots).map(|(&arg, &(_, ref ty))| match *ty {
+        Some(ref ty) if is_section(ty) => Some(Section { arg: arg, ty: ty }),
+        _ => None,
+    }).collect();
+    let routed: Vec<_> = sections.iter().zip(args).map(|(section, &arg)| match *section {
+        Some(ref section) => ArgumentV1::new(section, <Section as fmt::Display>::fmt),
+        None => arg,
+    }).collect();
+    f(&routed)
+}
@@ -0,0 +1,191 @@
+//! Prepared formats taken apart from the type they were prepared against.
+use alloc::borrow::Cow;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::rt::v1;
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
+use {nested, Error, FormatArgs, Parsed, PreparedArgument, PreparedFormat};
+
+/// The parsed form of a `PreparedFormat`, without the formatting functions
+/// which tie it to one type.
+///
+/// A shape may be taken from a format prepared ahead of time, stored, and
+/// later bound to the same type with `PreparedFormat::from_shape`, without
+/// parsing the format string again. With the `serde` feature, shapes may be
+/// serialized and deserialized.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct FormatShape {
+    spec: String,
+    pieces: Vec<String>,
+    // The index and format type of each argument, or `None` for a count.
+    args: Vec<(usize, Option<String>)>,
+    fmt: Option<Vec<Spec>>,
+    names: Vec<String>,
+    // Limits to clamp counts to, as given to `prepare_with_limits`.
+    count_limits: Vec<(usize, usize)>,
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+struct Spec {
+    position: usize,
+    fill: char,
+    align: Align,
+    flags: u32,
+    precision: Count,
+    width: Count,
+}
+
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+enum Align {
+    Left,
+    Right,
+    Center,
+    Unknown,
+}
+
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+enum Count {
+    Is(usize),
+    Param(usize),
+    Implied,
+}
+
+impl<'s, T: FormatArgs> PreparedFormat<'s, T> {
+    /// Take the shape of this prepared format, for binding again later.
+    pub fn shape(&self) -> FormatShape {
+        let inner = &self.inner;
+        FormatShape {
+            spec: String::from(&*inner.spec),
+            pieces: inner.pieces.iter().map(|p| String::from(&**p)).collect(),
+            args: inner.slots.iter()
+                .map(|&(idx, ref ty)| (idx, ty.as_ref().map(|ty| String::from(&**ty))))
+                .collect(),
+            fmt: inner.fmt.as_ref().map(|fmt| fmt.iter().map(|arg| Spec {
+                position: arg.position,
+                fill: arg.format.fill,
+                align: match arg.format.align {
+                    v1::Alignment::Left => Align::Left,
+                    v1::Alignment::Right => Align::Right,
+                    v1::Alignment::Center => Align::Center,
+                    v1::Alignment::Unknown => Align::Unknown,
+                },
+                flags: arg.format.flags,
+                precision: from_v1(arg.format.precision),
+                width: from_v1(arg.format.width),
+            }).collect()),
+            names: inner.names.iter().map(|n| String::from(&**n)).collect(),
+            count_limits: self.count_limits.clone(),
+        }
+    }
+
+    /// Bind a shape to this type, checking each argument as `prepare` would.
+    ///
+    /// The result borrows its text from the shape.
+    pub fn from_shape(shape: &'s FormatShape) -> Result<Self, Error<'s>> {
+        let mut args = Vec::with_capacity(shape.args.len());
+        let mut indices = Vec::new();
+        for &(idx, ref ty) in &shape.args {
+            if !T::validate_index(idx) && nested::<T>(idx).is_none() {
+                return Err(Error::BadIndex(idx))
+            }
+            args.push(match *ty {
+                Some(ref ty) => PreparedArgument::format(ty, idx)?,
+                None => PreparedArgument::format_usize(idx)
+                    .map_err(|reason| Error::BadCount { idx: idx, ty: None, reason: reason })?,
+            });
+            if let Err(pos) = indices.binary_search(&idx) {
+                indices.insert(pos, idx);
+            }
+        }
+
+        // Positions index into the arguments, and must find a value or a
+        // count as appropriate, in case the shape was tampered with.
+        let check = |pos: usize, count: bool| match shape.args.get(pos) {
+            Some(&(_, ref ty)) if ty.is_none() == count => Ok(()),
+            _ => Err(Error::BadIndex(pos)),
+        };
+        let fmt = match shape.fmt {
+            None => {
+                for pos in 0..args.len() {
+                    check(pos, false)?;
+                }
+                None
+            }
+            Some(ref fmt) => {
+                let mut converted = Vec::with_capacity(fmt.len());
+                for spec in fmt {
+                    check(spec.position, false)?;
+                    converted.push(v1::Argument {
+                        position: spec.position,
+                        format: v1::FormatSpec {
+                            fill: spec.fill,
+                            align: match spec.align {
+                                Align::Left => v1::Alignment::Left,
+                                Align::Right => v1::Alignment::Right,
+                                Align::Center => v1::Alignment::Center,
+                                Align::Unknown => v1::Alignment::Unknown,
+                            },
+                            flags: spec.flags,
+                            precision: to_v1(spec.precision, &check)?,
+                            width: to_v1(spec.width, &check)?,
+                        },
+                    });
+                }
+                Some(converted)
+            }
+        };
+        for &(pos, _) in &shape.count_limits {
+            check(pos, true)?;
+        }
+        // Parsing leaves a piece before each formatted argument, and perhaps
+        // one more after the last.
+        let formatted = fmt.as_ref().map_or(args.len(), |fmt| fmt.len());
+        if shape.pieces.len() != formatted && shape.pieces.len() != formatted + 1 {
+            return Err(Error::BadIndex(shape.pieces.len()))
+        }
+
+        Ok(PreparedFormat {
+            inner: Parsed {
+                spec: Cow::Borrowed(&shape.spec),
+                pieces: shape.pieces.iter().map(|p| Cow::Borrowed(&**p)).collect(),
+                borrowed: Some(shape.pieces.iter().map(|p| &**p).collect()),
+                args: args,
+                slots: shape.args.iter()
+                    .map(|&(idx, ref ty)| (idx, ty.as_ref().map(|ty| Cow::Borrowed(&**ty))))
+                    .collect(),
+                fmt: fmt,
+                names: shape.names.iter().map(|n| Cow::Borrowed(&**n)).collect(),
+                indices: indices,
+            },
+            count_limits: shape.count_limits.clone(),
+        })
+    }
+}
+
+fn from_v1(count: v1::Count) -> Count {
+    match count {
+        v1::Count::Is(n) => Count::Is(n),
+        v1::Count::Param(pos) => Count::Param(pos),
+        v1::Count::Implied => Count::Implied,
+        // never produced by parsing
+        v1::Count::NextParam => unreachable!(),
+    }
+}
+
+fn to_v1<'s, F>(count: Count, check: &F) -> Result<v1::Count, Error<'s>>
+    where F: Fn(usize, bool) -> Result<(), Error<'s>>
+{
+    Ok(match count {
+        Count::Is(n) => v1::Count::Is(n),
+        Count::Param(pos) => {
+            check(pos, true)?;
+            v1::Count::Param(pos)
+        }
+        Count::Implied => v1::Count::Implied,
+    })
+}
@@ -0,0 +1,338 @@
+//! The structure of format strings, for inspection without formatting.
+use alloc::string::String;
+use alloc::vec::Vec;
+#[cfg(feature = "json")]
+use core::fmt::Write;
+use choice;
+use erase;
+use fmt_macros as p;
+use Error;
+
+/// A piece of a parsed format string.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum FormatPiece {
+    /// Literal text, with brace escapes already resolved.
+    Literal(String),
+    /// An argument to be formatted.
+    Argument(ArgSpec),
+}
+
+/// An argument within a format string and the way it is to be formatted.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ArgSpec {
+    /// The argument to be formatted.
+    pub position: Position,
+    /// The fill character, if one was given.
+    pub fill: Option<char>,
+    /// The requested alignment.
+    pub align: Alignment,
+    /// Whether the `+` flag was given.
+    pub sign_plus: bool,
+    /// Whether the `-` flag was given.
+    pub sign_minus: bool,
+    /// Whether the `#` flag was given.
+    pub alternate: bool,
+    /// Whether the `0` flag was given.
+    pub zero_pad: bool,
+    /// The minimum width.
+    pub width: Count,
+    /// The precision, or maximum width for strings.
+    pub precision: Count,
+    /// The format type, such as `x` or `?`, or empty for `Display`.
+    pub ty: String,
+    /// For a conditional section such as `{name?prefix}`, the text written
+    /// before the argument when it is not blank.
+    pub prefix: Option<String>,
+}
+
+/// Which argument an `ArgSpec` refers to.
+///
+/// Implicit positions such as `{}` are resolved to indices.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Position {
+    /// The argument at the given index.
+    Index(usize),
+    /// The argument with the given name.
+    Name(String),
+}
+
+/// The alignment of an argument within its width.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Alignment {
+    /// Aligned to the left, as with `<`.
+    Left,
+    /// Aligned to the right, as with `>`.
+    Right,
+    /// Centered, as with `^`.
+    Center,
+    /// No alignment was given, leaving the default to the formatting trait.
+    Unknown,
+}
+
+/// A width or precision.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Count {
+    /// A literal count.
+    Is(usize),
+    /// The argument with the given name, as with `name$`.
+    Name(String),
+    /// The argument at the given index, as with `1$` or `.*`.
+    Param(usize),
+    /// No count was given.
+    Implied,
+}
+
+/// Parse a format string into its literal pieces and argument specs.
+///
+/// Only the syntax is checked, so any names and indices are accepted.
+pub fn parse_spec<'s>(spec: &'s str) -> Result<Vec<FormatPiece>, Error<'s>> {
+    let mut parser = p::Parser::new(spec);
+    let mut pieces = Vec::new();
+    for piece in &mut parser {
+        match piece {
+            p::Piece::String("") => {}
+            p::Piece::String(text) => match pieces.last_mut() {
+                Some(&mut FormatPiece::Literal(ref mut last)) => last.push_str(text),
+                _ => pieces.push(FormatPiece::Literal(text.into())),
+            },
+            p::Piece::NextArgument(arg) => pieces.push(FormatPiece::Argument(convert(arg))),
+        }
+    }
+    if parser.errors.is_empty() {
+        Ok(pieces)
+    } else {
+        Err(Error::BadSyntax(parser.errors))
+    }
+}
+
+/// List the trait each argument of a format string must implement, without
+/// a `FormatArgs` type or any values to check against.
+///
+/// Requirements are listed in order of first appearance, each once. The
+/// traits of the built-in format types are named as in `std::fmt` or the
+/// `custom` module, such as `Display` or `LowerHex`, and any other format
+/// type requires `CustomFormat`. An argument used as a width or precision
+/// requires `usize`, one selecting text with `plural(...)` requires
+/// `Into<i128>`, and a conditional section requires `Display`.
+///
+/// ```rust
+/// use runtime_fmt::spec::{required_traits, Position};
+///
+/// assert_eq!(required_traits("{} {:#x} {name:>w$}").unwrap(), [
+///     (Position::Index(0), "Display"),
+///     (Position::Index(1), "LowerHex"),
+///     (Position::Name("name".into()), "Display"),
+///     (Position::Name("w".into()), "usize"),
+/// ]);
+/// ```
+pub fn required_traits<'s>(spec: &'s str) -> Result<Vec<(Position, &'static str)>, Error<'s>> {
+    let mut required = Vec::new();
+    let mut require = |position: Position, name: &'static str| {
+        if !required.iter().any(|r: &(Position, &str)| r.0 == position && r.1 == name) {
+            required.push((position, name));
+        }
+    };
+    for piece in parse_spec(spec)? {
+        let arg = match piece {
+            FormatPiece::Argument(arg) => arg,
+            FormatPiece::Literal(_) => continue,
+        };
+        let needed = match (&arg.prefix, choice::parse(&arg.ty)) {
+            (&Some(_), _) => "Display",
+            (&None, Some(Ok(_))) => "Into<i128>",
+            (&None, Some(Err(_))) => return Err(Error::NoSuchFormat(arg.ty.into())),
+            (&None, None) => erase::trait_name(&arg.ty).unwrap_or("CustomFormat"),
+        };
+        require(arg.position, needed);
+        for count in &[arg.width, arg.precision] {
+            match *count {
+                Count::Name(ref name) => require(Position::Name(name.clone()), "usize"),
+                Count::Param(idx) => require(Position::Index(idx), "usize"),
+                Count::Is(_) | Count::Implied => {}
+            }
+        }
+    }
+    Ok(required)
+}
+
+/// Check that the braces of a format string are balanced, as a quick test
+/// which can be made at compile time.
+///
+/// Each `{` must either be escaped as `{{` or be closed by a `}` before the
+/// next brace, and each `}` outside of an argument must be escaped as `}}`.
+/// Nothing else is checked, so a string which passes may still be rejected
+/// by `parse_spec`.
+pub const fn validate_braces(spec: &str) -> bool {
+    let bytes = spec.as_bytes();
+    let mut i = 0;
+    let mut in_arg = false;
+    while i < bytes.len() {
+        let escaped = i + 1 < bytes.len() && bytes[i + 1] == bytes[i];
+        match bytes[i] {
+            b'{' if in_arg => return false,
+            b'{' if escaped => i += 1,
+            b'{' => in_arg = true,
+            b'}' if in_arg => in_arg = false,
+            b'}' if escaped => i += 1,
+            b'}' => return false,
+            _ => {}
+        }
+        i += 1;
+    }
+    !in_arg
+}
+
+/// Parse a format string and describe it as JSON, for tools outside of Rust.
+///
+/// The result is an array with an object for each piece: literal text is
+/// `{"literal": "..."}`, and an argument is
+///
+/// ```text
+/// {"arg": {"name": "count", "index": null, "spec": {"fill": null,
+///     "align": "right", "flags": ["+"], "width": 5, "precision": null,
+///     "type": "x"}, "prefix": null}}
+/// ```
+///
+/// An argument has either a `name` or an `index`, the other being `null`.
+/// `align` is `"left"`, `"right"`, `"center"`, or `null`, and `flags` lists
+/// any of `"+"`, `"-"`, `"#"` and `"0"` which were given. A width or
+/// precision is a number, `{"name": "..."}` or `{"index": n}` for one taken
+/// from an argument, or `null`. The `prefix` of a conditional section such as
+/// `{name?prefix}` is a string, and is `null` for any other argument.
+#[cfg(feature = "json")]
+pub fn to_json<'s>(spec: &'s str) -> Result<String, Error<'s>> {
+    let mut out = String::from("[");
+    for (i, piece) in parse_spec(spec)?.iter().enumerate() {
+        if i != 0 {
+            out.push_str(", ");
+        }
+        match *piece {
+            FormatPiece::Literal(ref text) => {
+                out.push_str("{\"literal\": ");
+                json_string(&mut out, text);
+                out.push('}');
+            }
+            FormatPiece::Argument(ref arg) => json_arg(&mut out, arg),
+        }
+    }
+    out.push(']');
+    Ok(out)
+}
+
+#[cfg(feature = "json")]
+fn json_arg(out: &mut String, arg: &ArgSpec) {
+    out.push_str("{\"arg\": {\"name\": ");
+    match arg.position {
+        Position::Index(idx) => {
+            let _ = write!(out, "null, \"index\": {}", idx);
+        }
+        Position::Name(ref name) => {
+            json_string(out, name);
+            out.push_str(", \"index\": null");
+        }
+    }
+    out.push_str(", \"spec\": {\"fill\": ");
+    match arg.fill {
+        Some(fill) => json_string(out, fill.encode_utf8(&mut [0; 4])),
+        None => out.push_str("null"),
+    }
+    out.push_str(", \"align\": ");
+    out.push_str(match arg.align {
+        Alignment::Left => "\"left\"",
+        Alignment::Right => "\"right\"",
+        Alignment::Center => "\"center\"",
+        Alignment::Unknown => "null",
+    });
+    out.push_str(", \"flags\": [");
+    let flags = [
+        (arg.sign_plus, "\"+\""),
+        (arg.sign_minus, "\"-\""),
+        (arg.alternate, "\"#\""),
+        (arg.zero_pad, "\"0\""),
+    ];
+    for (i, &(_, flag)) in flags.iter().filter(|f| f.0).enumerate() {
+        if i != 0 {
+            out.push_str(", ");
+        }
+        out.push_str(flag);
+    }
+    out.push_str("], \"width\": ");
+    json_count(out, &arg.width);
+    out.push_str(", \"precision\": ");
+    json_count(out, &arg.precision);
+    out.push_str(", \"type\": ");
+    json_string(out, &arg.ty);
+    out.push_str("}, \"prefix\": ");
+    match arg.prefix {
+        Some(ref prefix) => json_string(out, prefix),
+        None => out.push_str("null"),
+    }
+    out.push_str("}}");
+}
+
+#[cfg(feature = "json")]
+fn json_count(out: &mut String, count: &Count) {
+    let _ = match *count {
+        Count::Is(n) => write!(out, "{}", n),
+        Count::Name(ref name) => {
+            out.push_str("{\"name\": ");
+            json_string(out, name);
+            out.write_str("}")
+        }
+        Count::Param(idx) => write!(out, "{{\"index\": {}}}", idx),
+        Count::Implied => out.write_str("null"),
+    };
+}
+
+#[cfg(feature = "json")]
+fn json_string(out: &mut String, text: &str) {
+    out.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c < ' ' => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+fn convert(arg: p::Argument) -> ArgSpec {
+    let flag = |flag: p::Flag| arg.format.flags & (1 << flag as u32) != 0;
+    ArgSpec {
+        position: match arg.position {
+            p::ArgumentIs(idx) => Position::Index(idx),
+            p::ArgumentNamed(name) => Position::Name(name.into()),
+        },
+        fill: arg.format.fill,
+        align: match arg.format.align {
+            p::AlignLeft => Alignment::Left,
+            p::AlignRight => Alignment::Right,
+            p::AlignCenter => Alignment::Center,
+            p::AlignUnknown => Alignment::Unknown,
+        },
+        sign_plus: flag(p::FlagSignPlus),
+        sign_minus: flag(p::FlagSignMinus),
+        alternate: flag(p::FlagAlternate),
+        zero_pad: flag(p::FlagSignAwareZeroPad),
+        width: convert_count(arg.format.width),
+        precision: convert_count(arg.format.precision),
+        ty: arg.format.ty.into(),
+        prefix: arg.prefix.map(String::from),
+    }
+}
+
+fn convert_count(count: p::Count) -> Count {
+    match count {
+        p::CountIs(n) => Count::Is(n),
+        p::CountIsName(name) => Count::Name(name.into()),
+        p::CountIsParam(idx) => Count::Param(idx),
+        p::CountImplied => Count::Implied,
+    }
+}
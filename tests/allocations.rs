@@ -0,0 +1,74 @@
+#[macro_use] extern crate runtime_fmt_derive;
+extern crate runtime_fmt;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::fmt::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use runtime_fmt::{FormatBuf, Param, PreparedFormat};
+
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::SeqCst);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+fn allocations<F: FnOnce()>(f: F) -> usize {
+    let before = ALLOCATIONS.load(Ordering::SeqCst);
+    f();
+    ALLOCATIONS.load(Ordering::SeqCst) - before
+}
+
+#[derive(FormatArgs)]
+struct Entry {
+    key: &'static str,
+    value: i32,
+}
+
+// Only one test, so that no other test allocates while this one counts.
+#[test]
+fn with_small_lists() {
+    let mut out = String::with_capacity(256);
+    let mut write = |args: std::fmt::Arguments| {
+        out.clear();
+        out.write_fmt(args).unwrap();
+    };
+    let entry = Entry { key: "answer", value: 42 };
+
+    let prepared = PreparedFormat::<Entry>::prepare("{key} = {value}").unwrap();
+    assert_eq!(allocations(|| prepared.with(&entry, &mut write)), 0);
+    let escaped = PreparedFormat::<Entry>::prepare("{{{key}}} = {value}").unwrap();
+    assert_eq!(allocations(|| escaped.with(&entry, &mut write)), 0);
+
+    let params = [Param::normal(&"answer"), Param::normal(&42)];
+    let buf = FormatBuf::new("{} = {}", &params).unwrap();
+    assert_eq!(allocations(|| buf.with(&mut write)), 0);
+    let owned = FormatBuf::new_owned("{} = {}", vec![Param::owned("answer"), Param::owned(42)])
+        .unwrap();
+    assert_eq!(allocations(|| owned.with(&mut write)), 0);
+
+    // reparsing a buffer reuses its lists once they are large enough
+    let first = [Param::named("key", &"a"), Param::named("value", &1)];
+    let second = [Param::named("key", &"b"), Param::named("value", &2)];
+    let mut reused = FormatBuf::new("{key:>3} = {value}", &first).unwrap();
+    assert_eq!(allocations(|| reused.reparse("{key:>4} = {value}", &second).unwrap()), 0);
+    assert_eq!(allocations(|| reused.with(&mut write)), 0);
+
+    // longer lists are allocated
+    let many = PreparedFormat::<[u8; 9]>::prepare("{}{}{}{}{}{}{}{}{}").unwrap();
+    assert_eq!(allocations(|| many.with(&[1, 2, 3, 4, 5, 6, 7, 8, 9], &mut write)), 1);
+    drop(write);
+    assert_eq!(out, "123456789");
+}
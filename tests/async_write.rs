@@ -0,0 +1,63 @@
+#![cfg(feature = "async")]
+
+extern crate runtime_fmt;
+extern crate futures_io;
+
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+
+use futures_io::AsyncWrite;
+use runtime_fmt::{FormatBuf, Param};
+
+// Accepts at most three bytes per write, and is not ready every other time.
+#[derive(Default)]
+struct Trickle {
+    out: Vec<u8>,
+    ready: bool,
+}
+
+impl AsyncWrite for Trickle {
+    fn poll_write(mut self: Pin<&mut Self>, _: &mut Context, buf: &[u8]) -> Poll<io::Result<usize>> {
+        self.ready = !self.ready;
+        if !self.ready {
+            return Poll::Pending
+        }
+        let n = buf.len().min(3);
+        self.out.extend_from_slice(&buf[..n]);
+        Poll::Ready(Ok(n))
+    }
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+    fn poll_close(self: Pin<&mut Self>, _: &mut Context) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+fn block_on<F: Future>(future: F) -> F::Output {
+    fn raw() -> RawWaker {
+        fn clone(_: *const ()) -> RawWaker { raw() }
+        fn noop(_: *const ()) {}
+        static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+        RawWaker::new(std::ptr::null(), &VTABLE)
+    }
+    let waker = unsafe { Waker::from_raw(raw()) };
+    let mut cx = Context::from_waker(&waker);
+    let mut future = Box::pin(future);
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output
+        }
+    }
+}
+
+#[test]
+fn write_async() {
+    let mut dest = Trickle::default();
+    let params = [Param::normal(&"total"), Param::normal(&12.345)];
+    let buf = FormatBuf::new("{} = {:>6.2}", &params).unwrap();
+    block_on(buf.write_async(&mut dest)).unwrap();
+    assert_eq!(dest.out, b"total =  12.35");
+}
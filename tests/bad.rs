@@ -2,6 +2,27 @@
 
 use runtime_fmt::Error::*;
 
+// Destinations which refuse every write, through `fmt::Write` and through
+// `io::Write`.
+struct FullFmt;
+
+impl std::fmt::Write for FullFmt {
+    fn write_str(&mut self, _: &str) -> std::fmt::Result {
+        Err(std::fmt::Error)
+    }
+}
+
+struct FullIo;
+
+impl std::io::Write for FullIo {
+    fn write(&mut self, _: &[u8]) -> std::io::Result<usize> {
+        Err(std::io::Error::new(std::io::ErrorKind::WriteZero, "full"))
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
 macro_rules! err_with {
     ($err:expr; $($rest:tt)*) => {
         assert_eq!(
@@ -18,15 +39,71 @@ macro_rules! err_any {
 }
 
 #[test]
-fn bad_index() {
-    err_with!(BadIndex(0); "{}");
-    err_with!(BadIndex(7); "{7}");
-    err_with!(BadIndex(2); "{} {} {}", "", "");
+fn missing_argument() {
+    err_with!(MissingArgument { index: 0, supplied: 0 }; "{}");
+    err_with!(MissingArgument { index: 7, supplied: 0 }; "{7}");
+    err_with!(MissingArgument { index: 2, supplied: 2 }; "{} {} {}", "", "");
+    err_with!(MissingArgument { index: 2, supplied: 2 }; "{2}", 1, x = 2);
+    err_with!(MissingArgument { index: 3, supplied: 1 }; "{:3$}", 1);
+    assert_eq!(rt_format!("{1}", 1).unwrap_err().to_string(),
+               "argument 1 is missing; 1 were supplied");
+}
+
+#[test]
+fn short_params() {
+    use runtime_fmt::{FormatBuf, Param};
+
+    let params = [Param::normal(&1), Param::normal(&2), Param::normal(&3)];
+    let short = &params[..2];
+    for &(spec, idx) in &[("{2}", 2), ("{:.2$}", 2), ("{0:>5$}", 5), ("{2:plural(other#x)}", 2),
+                          ("{0:2$plural(other#x)}", 2), ("{} {} {}", 2)] {
+        match FormatBuf::new(spec, short) {
+            Err(MissingArgument { index, supplied: 2 }) => assert_eq!(index, idx, "{}", spec),
+            other => panic!("{}: expected MissingArgument, got {:?}", spec, other),
+        }
+    }
+
+    // a default stands in for names, not for positions
+    let default = Param::normal(&0);
+    for &(spec, idx) in &[("{2}", 2), ("{missing:.2$}", 2), ("{missing} {} {} {}", 2)] {
+        match FormatBuf::new_with_default(spec, short, &default) {
+            Err(MissingArgument { index, supplied: 2 }) => assert_eq!(index, idx, "{}", spec),
+            other => panic!("{}: expected MissingArgument, got {:?}", spec, other),
+        }
+    }
+
+    // counts taken from the parameters
+    let shorter = &params[..1];
+    for &(spec, idx) in &[("{:.*}", 1), ("{:1$}", 1), ("{0:.1$}", 1), ("{:>0$.*}", 1)] {
+        match FormatBuf::new(spec, shorter) {
+            Err(MissingArgument { index, supplied: 1 }) => assert_eq!(index, idx, "{}", spec),
+            other => panic!("{}: expected MissingArgument, got {:?}", spec, other),
+        }
+    }
 }
 
 #[test]
 fn bad_usize() {
-    err_with!(BadCount(0); "{:.*}", "Not A Usize", "aaaa");
+    use runtime_fmt::CountError;
+
+    let bad = |idx, ty, reason| BadCount { idx: idx, ty: Some(ty), reason: reason };
+    err_with!(bad(0, "&str", CountError::WrongType); "{:.*}", "Not A Usize", "aaaa");
+    err_with!(bad(0, "i32", CountError::Negative); "{:.*}", -1, "aaaa");
+    err_with!(bad(1, "u128", CountError::Overflow); "{:>1$}", "a", std::u128::MAX);
+    err_with!(bad(0, "i128", CountError::Negative); "{:.*}", std::i128::MIN, "a");
+    err_with!(bad(1, "i32", CountError::Negative); "{0:1$plural(other#x)}", 1, -3);
+    err_with!(bad(1, "&str", CountError::WrongType); "{0:1$plural(other#x)}", 1, "3");
+
+    assert_eq!(rt_format!("{:.1$}", "a", -2).unwrap_err().to_string(),
+               "argument 1 of type `i32` is negative and cannot be used as a count");
+    assert_eq!(rt_format!("{:.1$}", "a", std::u128::MAX).unwrap_err().to_string(),
+               "argument 1 of type `u128` is too large to be used as a count");
+    assert_eq!(rt_format!("{:.1$}", "a", 1.5).unwrap_err().to_string(),
+               "argument 1 of type `f64` cannot be used as a count");
+    assert_eq!(rt_format!("{} {:3$}", 1, 2, 3, "x").unwrap_err().to_string(),
+               "argument 3 of type `&str` cannot be used as a count");
+    assert_eq!(BadCount { idx: 2, ty: None, reason: CountError::WrongType }.to_string(),
+               "argument 2 cannot be used as a count");
 }
 
 #[test]
@@ -34,11 +111,246 @@ fn bad_syntax() {
     err_any!("{-1}");
 }
 
+#[test]
+fn fill_without_align() {
+    for spec in &["{:*}", "{:*5}", "{:_x}", "{:0*}"] {
+        match rt_format!(spec, 1) {
+            Err(BadSyntax(_)) => {}
+            other => panic!("{:?}: expected BadSyntax, got {:?}", spec, other),
+        }
+    }
+    // a letter is taken as a format type, as std does
+    err_with!(NoSuchFormat("é".into()); "{:é}", 1);
+    assert_eq!(rt_format!("{:*<}|{:*<3}|{:é^3}", 1, 2, 3).unwrap(), "1|2**|é3é");
+}
+
 #[test]
 fn bad_format() {
     struct Foo;
 
-    err_with!(NoSuchFormat("q"); "{:q}", "");
+    err_with!(NoSuchFormat("q".into()); "{:q}", "");
     err_with!(UnsatisfiedFormat { idx: 0, must_implement: "Debug" };
         "{:?}", Foo);
 }
+
+#[test]
+fn bad_syntax_span() {
+    let spec = "{0} }{1";
+    match rt_format!(spec, 1, 2) {
+        Err(BadSyntax(errors)) => {
+            let spans: Vec<_> = errors.iter().map(|e| &spec[e.2.clone()]).collect();
+            assert_eq!(spans, ["}"]);
+        }
+        other => panic!("expected BadSyntax, got {:?}", other),
+    }
+}
+
+#[test]
+fn owned_error() {
+    fn check(spec: &str) -> runtime_fmt::OwnedError {
+        rt_format!(spec, x=1).unwrap_err().into_owned()
+    }
+    let errors = vec![check("{y}"), check("{:q}"), check("{")];
+    match errors[0] { BadName(ref n) => assert_eq!(n, "y"), ref e => panic!("{:?}", e) }
+    match errors[1] { NoSuchFormat(ref c) => assert_eq!(c, "q"), ref e => panic!("{:?}", e) }
+    match errors[2] { BadSyntax(_) => {}, ref e => panic!("{:?}", e) }
+}
+
+#[test]
+fn clone_errors() {
+    use std::io;
+
+    let errors = ["{", "{x}", "{:q}", "{0:1$}", "{5}"].iter()
+        .map(|spec| rt_format!(spec, "a", "b").unwrap_err().into_owned())
+        .collect::<Vec<_>>();
+    for error in &errors {
+        assert_eq!(format!("{:?}", error.clone()), format!("{:?}", error));
+    }
+    assert_eq!(errors.clone()[1].to_string(), "unknown name \"x\"");
+
+    let io = Io(io::Error::new(io::ErrorKind::WriteZero, "full"));
+    match io.clone() {
+        Io(ref e) if e.kind() == io::ErrorKind::WriteZero => assert_eq!(e.to_string(), "full"),
+        other => panic!("{:?}", other),
+    }
+    match Io(io::Error::from_raw_os_error(2)).clone() {
+        Io(ref e) => assert_eq!(e.raw_os_error(), Some(2)),
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn into_io_error() {
+    use std::io::{self, Write};
+
+    fn write_line(dest: &mut Vec<u8>, spec: &str) -> io::Result<()> {
+        rt_writeln!(dest, spec, x=1)?;
+        Ok(())
+    }
+    let mut dest = Vec::new();
+    write_line(&mut dest, "{x}").unwrap();
+    assert_eq!(dest, b"1\n");
+    let err = write_line(&mut dest, "{y}").unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    assert_eq!(err.to_string(), "unknown name \"y\"");
+
+    let err: io::Error = rt_write!(FullIo, "{}", 1).unwrap_err().into();
+    assert_eq!(err.kind(), io::ErrorKind::WriteZero);
+}
+
+#[test]
+fn fmt_at() {
+    use std::fmt::{self, Display, Write};
+
+    struct Failing;
+    impl Display for Failing {
+        fn fmt(&self, _: &mut fmt::Formatter) -> fmt::Result {
+            Err(fmt::Error)
+        }
+    }
+    let mut out = String::new();
+    match rt_write!(out, "{} {}", 1, Failing) {
+        Err(FmtAt { idx: 1, .. }) => {}
+        other => panic!("{:?}", other),
+    }
+    match rt_writeln!(out, "{1:>5} {x} {0}", 1, 2, x=Failing) {
+        Err(ref e @ FmtAt { idx: 2, .. }) => assert!(e.to_string().ends_with(" in argument 2")),
+        other => panic!("{:?}", other),
+    }
+    match rt_format_to!(out, "{1} {0}", Failing, Failing) {
+        Err(FmtAt { idx: 1, .. }) => {}
+        other => panic!("{:?}", other),
+    }
+
+    // a failing destination is not blamed on an argument
+    match rt_write!(FullFmt, "{}", 1) {
+        Err(Fmt(_)) => {}
+        other => panic!("{:?}", other),
+    }
+    assert_eq!(rt_format_args!("{} {}", 1, Failing).unwrap().failing_argument(), Some(1));
+    assert_eq!(rt_format_args!("{} {}", 1, 2).unwrap().failing_argument(), None);
+
+    // a failing default is blamed on the name it stood in for
+    let params = [runtime_fmt::Param::named("a", &1)];
+    let failing = runtime_fmt::Param::normal(&Failing);
+    let buf = runtime_fmt::FormatBuf::new_with_default("{a} {b} {c}", &params, &failing).unwrap();
+    let idx = buf.failing_argument().unwrap();
+    assert_eq!(buf.defaulted_name(idx), Some("b"));
+    assert_eq!(buf.defaulted_name(0), None);
+    match buf._write_error(fmt::Error) {
+        Defaulted { ref name, ref source } => {
+            assert_eq!(name, "b");
+            match **source {
+                FmtAt { idx: 0, .. } => {}
+                ref other => panic!("{:?}", other),
+            }
+        }
+        other => panic!("{:?}", other),
+    }
+
+    let first = runtime_fmt::FormatBuf::new_with_default("{a}", &params, &failing).unwrap();
+    let second = runtime_fmt::FormatBuf::new_with_default("{c}", &params, &failing).unwrap();
+    let buf = first.concat(second);
+    assert_eq!(buf.defaulted_name(buf.failing_argument().unwrap()), Some("c"));
+}
+
+#[test]
+fn error_source() {
+    use std::error::Error as StdError;
+    use std::fmt::Write;
+
+    let err = rt_write!(FullFmt, "{}", 1).unwrap_err().into_owned();
+    assert!(err.source().unwrap().is::<std::fmt::Error>());
+    assert!(rt_format!("{}").unwrap_err().source().is_none());
+
+    // the failure behind a defaulted name is the next link in the chain
+    let params = [runtime_fmt::Param::named("a", &1)];
+    let default = runtime_fmt::Param::normal(&"none");
+    let err = runtime_fmt::FormatBuf::new_with_default("{a} {b:x}", &params, &default)
+        .unwrap_err();
+    let mut chain = vec![err.to_string()];
+    let mut next = err.source();
+    while let Some(e) = next {
+        chain.push(e.to_string());
+        next = e.source();
+    }
+    assert_eq!(chain.len(), 2);
+    assert_eq!(chain[1], UnsatisfiedFormat { idx: 0, must_implement: "LowerHex" }.to_string());
+    match err.source().and_then(|e| e.downcast_ref::<runtime_fmt::OwnedError>()) {
+        Some(&UnsatisfiedFormat { idx: 0, .. }) => {}
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn collect_errors() {
+    use runtime_fmt::{FormatBuf, Param};
+
+    let params = [Param::named("name", &"x"), Param::normal(&1)];
+    let errors = FormatBuf::new_collecting("{nmae} {name} {3} {:q} {1:.nme$}", &params)
+        .unwrap_err();
+    let errors: Vec<_> = errors.iter().map(|e| format!("{:?}", e)).collect();
+    assert_eq!(errors, [
+        "BadName(\"nmae\")",
+        "MissingArgument { index: 3, supplied: 2 }",
+        "NoSuchFormat(\"q\")",
+        "BadName(\"nme\")",
+    ]);
+
+    match FormatBuf::new_collecting("{nmae} }", &params) {
+        Err(ref errors) if errors.len() == 1 => match errors[0] {
+            BadSyntax(_) => {}
+            ref e => panic!("{:?}", e),
+        },
+        Err(errors) => panic!("{:?}", errors),
+        Ok(_) => panic!(),
+    }
+    assert!(FormatBuf::new_collecting("{name} {}", &params).is_ok());
+}
+
+#[test]
+fn suggest_escape() {
+    for spec in &["a { b", "a {", "a } b"] {
+        match rt_format!(*spec) {
+            Err(BadSyntax(ref errors)) => {
+                let note = errors[0].1.as_ref().expect("a note");
+                assert!(note.contains("{{") || note.contains("}}"), "{}", note);
+            }
+            other => panic!("expected BadSyntax, got {:?}", other),
+        }
+    }
+}
+
+#[test]
+fn no_such_format() {
+    let err = rt_format!("{:q}", 1).unwrap_err().into_owned();
+    assert_eq!(err.to_string(), "bad formatting specifier \"q\"; known specifiers are \
+        \"\", \"?\", \"e\", \"E\", \"o\", \"p\", \"b\", \"x\", \"X\", \"grp\", \"hex\", \"b64\"");
+    assert!(err.suggestions().is_empty());
+
+    fn suggest(spec: &str) -> Vec<&'static str> {
+        rt_format!(spec, 1).unwrap_err().suggestions()
+    }
+    assert_eq!(suggest("{:gpr}"), ["grp"]);
+    assert_eq!(suggest("{:gr}"), ["grp"]);
+    assert_eq!(suggest("{:hx}"), ["x", "hex"]);
+    assert_eq!(suggest("{:xx}"), ["x"]);
+    assert_eq!(suggest("{:Xx}"), ["x", "X"]);
+    assert!(suggest("{:hex}").is_empty());
+}
+
+#[test]
+fn unterminated() {
+    let cases = [("{", 0), ("{0", 0), ("{0:", 0), ("{0:>", 0), ("hello {", 6), ("{0} {x:.1$", 4)];
+    for &(spec, pos) in &cases {
+        match rt_format!(spec, 1, 2, x=3) {
+            Err(BadSyntax(errors)) => {
+                assert_eq!(errors.len(), 1, "{:?}", errors);
+                assert_eq!(errors[0].0, format!("unterminated format specifier at position {}", pos));
+                assert!(errors[0].1.as_ref().expect("a note").contains("{{"));
+                assert_eq!(errors[0].2, spec.len()..spec.len());
+            }
+            other => panic!("{:?}: expected BadSyntax, got {:?}", spec, other),
+        }
+    }
+}
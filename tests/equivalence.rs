@@ -30,4 +30,126 @@ fn simple_equivalence() {
     case!("Hex: {:.>4x}", 17);
     case!("{:p}", "Hello");
     case!("{}{}{}", "(A)", "_ _", "(B)");
-}
\ No newline at end of file
+}
+#[test]
+fn pretty_debug() {
+    #[derive(Debug)]
+    struct Inner {
+        values: Vec<u8>,
+        label: Option<&'static str>,
+    }
+    #[derive(Debug)]
+    struct Outer {
+        id: u32,
+        inner: Inner,
+    }
+
+    let x = Outer { id: 7, inner: Inner { values: vec![1, 2], label: Some("hi") } };
+    case!("{:#?}", x);
+    case!("{:?} / {:#?}", x, x);
+    case!("{0:#?}", (1, "two", [3.0]));
+}
+#[test]
+fn flags() {
+    case!("{:+} {:+} {:+}", 5, -5, 0);
+    case!("{:08} {:08} {:+08}", 42, -42, 42);
+    case!("{:+08.2} {:+08.2} {:08.3}", 3.14159, -3.14159, 2.5f32);
+    case!("{:+.0} {:+e} {:010e}", 0.5, 1234.5, -0.001);
+    case!("{:#x} {:#X} {:#o} {:#b}", 255, 255, 8, 5);
+    case!("{:#010x} {:#010b} {:+#06x}", 255, 5, 17);
+    case!("{:-} {:-5} {:<-5}|", 1, 2, 3);
+    case!("{:+>6} {:0>+6} {:^+8.1}", 7, 7, 1.25);
+    case!("{:#?} {:+?} {:08?}", Some(1), -1.5, 2);
+}
+#[test]
+fn default_alignment() {
+    case!("{:5}|{:5}|{:5}|{:5}|{:5}|", 42, -1.5, "x", 'c', true);
+    case!("{:5?}|{:5?}|{:5x}|{:5e}|", 42, "x", 255, 1.5);
+    case!("{:<5}|{:>5}|{:^5}|{:<5}|{:>5}|{:^5}|", 42, 42, 42, "x", "x", "x");
+    case!("{:*<5}|{:*>5}|{:*^6}|{:x<5}|", 42, "x", true, "ab");
+    case!("{:05}|{:<05}|{:^05}|", 42, -42, 7);
+    case!("{0:1$}|{0:<1$}|{2:1$}|", 9, 4, "y");
+}
+#[test]
+fn implicit_positions() {
+    case!("{} {2} {}", 'a', 'b', 'c');
+    case!("{1} {} {0} {}", 'a', 'b');
+    case!("{0} {} {} {0}", 'a', 'b');
+    case!("{x} {} {x} {}", 'a', 'b', x = 'c');
+    case!("{2} {} {1} {}|", 'a', 'b', 'c');
+    case!("{:.*} {}", 2, 1.5, 'z');
+    case!("{1:.*} {}", 3, 0.25);
+    case!("{} {:.*} {}", 'a', 1, 2.5, 'z');
+    case!("{:1$} {} {}", 'a', 4, 'b');
+}
+#[test]
+fn next_param_precision() {
+    case!("{:.*}|{:.*}|", 0, 2.5, 3, "abcdef");
+    case!("{:>8.*}|{:<+8.*}|{:^08.*}|", 2, 3.14159, 1, 2.5, 3, -1.0);
+    case!("{:.*?}|{:#.*?}", 1, 0.25, 2, (1.0, "xyz"));
+    case!("{:.*} {0} {:.*}", 1, 1.26, 2, 9.999);
+    case!("{:3$.*}|{}|", 2, 1.5, 'x', 9);
+    case!("{x:.*} {}", 2, 'b', x = 0.5);
+    case!("{:.*}", 10, "short");
+}
+#[test]
+fn fill_with_zero_pad() {
+    // std accepts a fill alongside the `0` flag, which then takes priority
+    // for numbers and is ignored for strings
+    case!("[{:x<08}] [{:*^+08.2}] [{:_>08}] [{:x<08}]", 5, 1.5, -3, "ab");
+    case!("[{:x<#010x}] [{:0<08}] [{:é^08?}]", 255, 7, -2.5);
+    case!("[{:x<0w$}] [{:y>0.*}]", 12, 3, 0.125, w = 6);
+}
+#[test]
+fn scientific() {
+    let subnormal = 1e-310f64;
+    let nan = std::f64::NAN;
+    let inf = std::f64::INFINITY;
+    case!("{:.3e} {:.3E} {:e} {:E}", 12345.678, 12345.678, 12345.678, 12345.678);
+    case!("{:.0e} {:.10e} {:.2E}", 0.5, 1.0 / 3.0, 9.999);
+    case!("{:e} {:.3e} {:E}", subnormal, subnormal, std::f64::MIN_POSITIVE);
+    case!("{:e} {:E} {:.3e} {:+e}", inf, -inf, nan, nan);
+    case!("{:e} {:.2E} {:+e}", -0.0, -0.0f32, 0.0);
+    case!("{:12.3e}|{:<12.3E}|{:+012.1e}|", 12345.678, -0.000123, 6.02e23);
+    case!("{:.*e} {3:.2$E}", 2, 12345.678, 4, 1.5e-7);
+}
+#[test]
+fn named_counts() {
+    case!("{val:w$.p$}|", val = 3.14159, w = 10, p = 2);
+    case!("{val:p$.w$}|", val = 3.14159, w = 10, p = 2);
+    case!("{val:>w$.p$}|{val:<p$.w$}|", val = "abcdefghijkl", w = 8, p = 3);
+    case!("{val:n$.n$}|{val:0n$}|", val = 2.5, n = 4);
+    case!("{p} {val:w$.p$} {w}", val = 1.0, w = 7, p = 3);
+    case!("{:w$.p$} {:p$.w$}", 0.5, "long string", w = 9, p = 2);
+    case!("{0:1$.2$}|{0:2$.1$}|", 1.23456, 8, 3);
+    case!("{2:0$.1$}|{2:1$}|", 6, 2, 9.875);
+    case!("{:1$.2$} {} {}", 0.125, 7, 1);
+    case!("{0:w$.2$} {val:1$.p$}", 4.5, 6, 1, val = 0.75, w = 5, p = 3);
+}
+#[test]
+fn pointers() {
+    let x = 5;
+    let mut y = [1u8, 2];
+    let boxed = Box::new(2.5);
+    let constant: *const i32 = &x;
+    let mutable: *mut [u8; 2] = &mut y;
+    case!("{:p}", &x);
+    case!("{:p}", constant);
+    case!("{:p}", mutable);
+    case!("{:p}", boxed);
+    case!("{:p} {:p} {0:p}", &x, &boxed);
+    case!("{:>20p}|{:<#20p}|", constant, mutable);
+    case!("{:p}", std::ptr::null::<u8>());
+}
+#[test]
+fn alternate_prefixes() {
+    assert_eq!(rt_format!("{:#x}", 255).unwrap(), "0xff");
+    assert_eq!(rt_format!("{:#X}", 255).unwrap(), "0xFF");
+    assert_eq!(rt_format!("{:#b}", 5).unwrap(), "0b101");
+    assert_eq!(rt_format!("{:#o}", 8).unwrap(), "0o10");
+    case!("{:#x} {:#X} {:#b} {:#o}", 0, 0u8, 0i64, 0u128);
+    case!("{:#x} {:#b} {:#o}", -1i8, -2i16, -3i32);
+    case!("{:#10x}|{:<#10b}|{:^#10o}|{:#010X}|", 255, 5, 64, 255);
+    case!("{0:#x} {0:x} {0:#?} {0:#X}", 171);
+    case!("{:+#x} {:#0w$o}", 16, 9, w = 8);
+}
@@ -9,12 +9,43 @@ macro_rules! t {
     }}
 }
 
+#[test]
+fn escaped_braces() {
+    t!("{value}"; "{{{}}}", "value");
+    t!("{}"; "{{}}");
+    t!("}{"; "}}{{");
+    t!("{{x}} {x}"; "{{{{x}}}} {{{}}}", 'x');
+}
+
 #[test]
 fn non_usize_pad() {
     t!("aaaa"; "{:.*}", 4u8, "aaaaaaaa");
     t!("aaaa"; "{:.*}", 4u16, "aaaaaaaa");
     t!("aaaa"; "{:.*}", 4u32, "aaaaaaaa");
     t!("aaaa"; "{:.*}", 4u64, "aaaaaaaa");
+    t!("aaaa"; "{:.*}", 4u128, "aaaaaaaa");
+    t!("aaaa"; "{:.*}", 4i32, "aaaaaaaa");
+    t!("aaaa"; "{:.*}", std::num::NonZeroUsize::new(4).unwrap(), "aaaaaaaa");
+    t!("  a"; "{:>1$}", "a", 3u16);
+}
+
+#[test]
+fn param_counts() {
+    use runtime_fmt::Param;
+
+    let counts = [Param::normal(&4u8), Param::owned(7i64), Param::named("n", &3usize),
+                  Param::owned(std::num::NonZeroUsize::new(2).unwrap())];
+    for param in &counts {
+        assert!(param.is_count());
+    }
+    assert_eq!(counts.iter().map(Param::as_count).collect::<Vec<_>>(),
+               [Some(4), Some(7), Some(3), Some(2)]);
+
+    for param in &[Param::normal(&-1), Param::normal(&"4"), Param::normal(&4.0),
+                   Param::owned(std::u128::MAX)] {
+        assert!(!param.is_count());
+        assert_eq!(param.as_count(), None);
+    }
 }
 
 #[test]
@@ -32,3 +63,963 @@ fn unused_is_fine() {
     t!("2"; rt_format!("{b}", a=1, b=2).unwrap());
     t!("3 1"; rt_format!("{} {a}", 3, 4, a=1, b=2).unwrap());
 }
+
+#[test]
+fn write_to_vec() {
+    let mut packet = b"HDR:".to_vec();
+    let written = rt_format_args!("{}={:04}", "len", 7).unwrap()
+        .write_to_vec(&mut packet).unwrap();
+    assert_eq!(written, 8);
+    assert_eq!(packet, b"HDR:len=0007");
+}
+
+#[test]
+fn formatted_len() {
+    assert_eq!(rt_format_args!("{}={:04}", "len", 7).unwrap().formatted_len(), 8);
+    assert_eq!(rt_format_args!("{:>5}", "\u{e9}").unwrap().formatted_len(), 6);
+    assert_eq!(rt_format_args!("").unwrap().formatted_len(), 0);
+}
+
+#[test]
+fn format_into() {
+    let mut doc = String::from("Title\n");
+    rt_format_args!("{}. {}\n", 1, "one").unwrap().format_into(&mut doc);
+    rt_format_args!("{}. {}\n", 2, "two").unwrap().format_into(&mut doc);
+    assert_eq!(doc, "Title\n1. one\n2. two\n");
+}
+
+#[test]
+fn map_params() {
+    use std::collections::HashMap;
+    use runtime_fmt::{FormatBuf, Param};
+
+    let mut config = HashMap::new();
+    config.insert("user".to_owned(), "alice".to_owned());
+    config.insert("host".to_owned(), "example.org".to_owned());
+
+    let params = Param::from_map(&config);
+    let buf = FormatBuf::new("{user}@{host}", &params).unwrap();
+    assert_eq!(buf.format(), "alice@example.org");
+    assert!(FormatBuf::new("{port}", &params).is_err());
+}
+
+#[test]
+fn slice_params() {
+    use std::fmt::Display;
+    use runtime_fmt::{FormatBuf, Param};
+
+    let values: Vec<u32> = (10..15).collect();
+    let params = Param::from_slice(&values);
+    let buf = FormatBuf::new("{4} {0:x} {:>0$}", &params).unwrap();
+    assert_eq!(buf.format(), "14 a         10");
+    match FormatBuf::new("{5}", &params) {
+        Err(runtime_fmt::Error::MissingArgument { index: 5, supplied: 5 }) => {}
+        other => panic!("{:?}", other),
+    }
+
+    let mixed: [&dyn Display; 3] = [&1, &"two", &3.5];
+    let params = Param::from_slice(&mixed[..]);
+    assert_eq!(FormatBuf::new("{}, {}, {}", &params).unwrap().format(), "1, two, 3.5");
+    assert!(FormatBuf::new("{1:x}", &params).is_err());
+}
+
+#[test]
+fn ordered_map_params() {
+    use std::collections::BTreeMap;
+    use runtime_fmt::{FormatBuf, Param};
+
+    let mut config = BTreeMap::new();
+    config.insert("user".to_owned(), "alice".to_owned());
+    config.insert("host".to_owned(), "example.org".to_owned());
+    config.insert("port".to_owned(), "22".to_owned());
+
+    let params = Param::from_map(&config);
+    let buf = FormatBuf::new("{0}:{1} {2}", &params).unwrap();
+    assert_eq!(buf.format(), "example.org:22 alice");
+    let buf = FormatBuf::new("{user}@{0}:{port}", &params).unwrap();
+    assert_eq!(buf.format(), "alice@example.org:22");
+    match FormatBuf::new("{3}", &params) {
+        Err(runtime_fmt::Error::MissingArgument { index: 3, supplied: 3 }) => {}
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn introspection() {
+    use runtime_fmt::{FormatBuf, Param};
+
+    let params = [Param::normal(&"x"), Param::normal(&5)];
+    let buf = FormatBuf::new("a{{b {} c {:>1$}", &params).unwrap();
+    assert_eq!(buf.arg_count(), 3);
+    assert_eq!(buf.pieces().collect::<Vec<_>>(), ["a{b ", " c "]);
+    assert_eq!(buf.len_hint(), 7);
+    assert_eq!(buf.spec(), "a{{b {} c {:>1$}");
+    assert!(buf.format().len() >= buf.len_hint());
+    assert!(buf.format().capacity() >= buf.len_hint());
+
+    let buf = FormatBuf::new("no arguments here", &[]).unwrap();
+    assert_eq!(buf.arg_count(), 0);
+    assert_eq!(buf.pieces().collect::<Vec<_>>(), ["no arguments here"]);
+    assert_eq!(buf.len_hint(), buf.format().len());
+}
+
+#[test]
+fn single_argument() {
+    use runtime_fmt::{FormatBuf, Param};
+
+    let params = [Param::normal(&"a\tb")];
+    for &(fast, general) in &[("{}", "{0}"), ("{:?}", "{0:?}")] {
+        let fast = FormatBuf::new(fast, &params).unwrap();
+        let general = FormatBuf::new(general, &params).unwrap();
+        assert_eq!(fast.format(), general.format());
+        assert_eq!(fast.arg_count(), general.arg_count());
+        assert_eq!(fast.pieces().collect::<Vec<_>>(), general.pieces().collect::<Vec<_>>());
+        assert!(fast.is_fully_borrowed());
+    }
+    let mut buf = FormatBuf::new("{}", &params).unwrap();
+    buf.append("!");
+    assert_eq!(buf.format(), "a\tb!");
+    assert_eq!(buf.spec(), "{}!");
+
+    match FormatBuf::new("{}", &[]) {
+        Err(runtime_fmt::Error::MissingArgument { index: 0, supplied: 0 }) => {}
+        other => panic!("{:?}", other),
+    }
+    let list = vec![1];
+    let params = [Param::normal(&list)];
+    assert_eq!(FormatBuf::new("{:?}", &params).unwrap().format(), "[1]");
+    match FormatBuf::new("{}", &params) {
+        Err(runtime_fmt::Error::UnsatisfiedFormat { idx: 0, .. }) => {}
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn fully_borrowed() {
+    use runtime_fmt::{FormatBuf, Param};
+
+    let params = [Param::normal(&1)];
+    let mut buf = FormatBuf::new("a {} b", &params).unwrap();
+    assert!(buf.is_fully_borrowed());
+    buf.newln();
+    assert!(!buf.is_fully_borrowed());
+    assert!(FormatBuf::new("{{x {}", &params).unwrap().is_fully_borrowed());
+    assert!(!FormatBuf::new("a{{b {}", &params).unwrap().is_fully_borrowed());
+    assert!(FormatBuf::new("x {:plural(1#one|other)}", &params).unwrap().is_fully_borrowed());
+}
+
+#[test]
+fn repeated_arguments() {
+    use runtime_fmt::{FormatBuf, Param};
+
+    let params = [Param::normal(&7), Param::normal(&3)];
+    let buf = FormatBuf::new("{0} {0} {0}", &params).unwrap();
+    assert_eq!(buf.arg_count(), 1);
+    assert_eq!(buf.format(), "7 7 7");
+
+    let buf = FormatBuf::new("{1}{0:x}{1:>0$}{0}{0:x}", &params).unwrap();
+    assert_eq!(buf.arg_count(), 4);
+    assert_eq!(buf.format(), "37      377");
+}
+
+#[test]
+fn grouped() {
+    t!("0"; "{:grp}", 0);
+    t!("999"; "{:grp}", 999u16);
+    t!("1,000,000"; "{:grp}", 1000000);
+    t!("-12,345"; "{:grp}", -12345i64);
+    t!("-170,141,183,460,469,231,731,687,303,715,884,105,728"; "{:grp}", std::i128::MIN);
+    t!("1_234_567"; "{:_<grp}", 1234567u32);
+    t!("1\u{2009}234"; "{:\u{2009}<grp}", 1234);
+    t!("  +1,234"; "{:+8grp}", 1234);
+    t!("__-1_234"; "{:_>8grp}", -1234);
+    assert!(rt_format!("{:grp}", 1.5).is_err());
+}
+
+#[test]
+fn localized() {
+    use runtime_fmt::custom::{Locale, Localized};
+    let de = Locale { decimal: ',', group: '.' };
+    let l = |x: f64| Localized(x, de);
+    t!("1,5"; "{}", l(1.5));
+    t!("3,14"; "{:.2}", l(3.14159));
+    t!("-002,50"; "{:07.2}", l(-2.5));
+    t!("..1,5.."; "{:.^7}", l(1.5));
+    t!("1,5e-7 1,5E7"; "{:e} {:E}", l(1.5e-7), l(1.5e7));
+    let inf = std::f64::INFINITY;
+    t!("NaN inf -inf"; "{} {} {}", l(std::f64::NAN), l(inf), l(-inf));
+    t!("1.234.567,5"; "{:grp}", l(1234567.5));
+    t!("  -1.234"; "{:8grp}", Localized(-1234, de));
+    t!("+1.000,00"; "{:+.2grp}", l(1000.0));
+    t!("  NaN"; "{:>5grp}", l(std::f64::NAN));
+    t!("1.5 v1.2"; "{} {}", 1.5, "v1.2");
+    t!("1,234.5"; "{:grp}", Localized(1234.5, Locale::default()));
+}
+
+#[test]
+fn bytes() {
+    let bytes: [u8; 6] = [0xde, 0xad, 0xbe, 0xef, 0x00, 0x7f];
+    t!("deadbeef007f"; "{:hex}", &bytes[..]);
+    t!("0xde 0xad 0xbe 0xef 0x00 0x7f"; "{:#hex}", &bytes[..]);
+    t!("0x01"; "{:#hex}", vec![1u8]);
+    t!(""; "{:hex}", Vec::<u8>::new());
+
+    t!(""; "{:b64}", &b""[..]);
+    t!("Zg=="; "{:b64}", &b"f"[..]);
+    t!("Zm8="; "{:b64}", &b"fo"[..]);
+    t!("Zm9v"; "{:b64}", &b"foo"[..]);
+    t!("Zm9vYmFy"; "{:b64}", b"foobar".to_vec());
+    t!("3q2+7wB/"; "{:b64}", &bytes[..]);
+
+    assert!(rt_format!("{:b64}", 1).is_err());
+}
+
+#[test]
+fn plural() {
+    let spec = "{n} {n:plural(0#no items|1#item|-1#debt|other#items)}";
+    for &(n, out) in &[(0, "0 no items"), (1, "1 item"), (-1, "-1 debt"), (7, "7 items"), (-7, "-7 items")] {
+        assert_eq!(rt_format!(spec, n=n).unwrap(), out);
+    }
+    t!("3 files"; "{} {0:plural(1#file|files)}", 3u8);
+    t!("[one  ]"; "[{:plural(1#one|other)}{2:<1$}]", 1usize, 2, "");
+    t!("[  one][ot]"; "[{0:>5plural(1#one|other)}][{1:.2plural(1#one|other)}]", 1, 2);
+    t!("[_one_][ot]"; "[{0:_^w$plural(1#one|other)}][{1:.p$plural(1#one|other)}]", 1, 2, w=5, p=2);
+
+    assert!(rt_format!("{:plural(1#one)}", 1).is_err());
+    assert!(rt_format!("{:plural(x#one|other)}", 1).is_err());
+    assert!(rt_format!("{:plural(1#one|other)}", "1").is_err());
+    assert!(rt_format!("{:.1$plural(1#one|other)}", 1, -1).is_err());
+}
+
+#[test]
+fn conditional_sections() {
+    let spec = "{name}{street?\n}{city?\n}{phone?\nTel: }";
+    t!("Ann\nMain St\nTel: 555"; spec, name="Ann", street="Main St", city="", phone=555);
+    t!("Ann\nSpringfield"; spec, name="Ann", street="  ", city="Springfield", phone="");
+    t!("[ x]"; "[{?}]", " x");
+    t!("a, b"; "{}{1?, }", "a", "b");
+    t!("a"; "{}{1?, }", "a", "");
+    let params = vec![runtime_fmt::Param::normal(&"b")];
+    assert_eq!(runtime_fmt::FormatBuf::new_owned("a{0?, }", params).unwrap().format(), "a, b");
+
+    assert!(rt_format!("{x?}", 1).is_err());
+    assert!(rt_format!("{0?a{b}", 1).is_err());
+    assert!(rt_format!("{0?yes}", vec![1]).is_err());
+}
+
+#[test]
+fn required_traits() {
+    use runtime_fmt::spec::{required_traits, Position};
+
+    assert_eq!(required_traits("{0} {0:?} {0} {1:e}{:grp}{x:mine} {:.*}").unwrap(), [
+        (Position::Index(0), "Display"),
+        (Position::Index(0), "Debug"),
+        (Position::Index(1), "LowerExp"),
+        (Position::Index(0), "Grouped"),
+        (Position::Name("x".into()), "CustomFormat"),
+        (Position::Index(2), "Display"),
+        (Position::Index(1), "usize"),
+    ]);
+    assert_eq!(required_traits("{n:plural(1#one|other)}{tel?, }{:1$}").unwrap(), [
+        (Position::Name("n".into()), "Into<i128>"),
+        (Position::Name("tel".into()), "Display"),
+        (Position::Index(0), "Display"),
+        (Position::Index(1), "usize"),
+    ]);
+    assert_eq!(required_traits("just text").unwrap(), []);
+    assert!(required_traits("{:plural(x#one)}").is_err());
+    assert!(required_traits("{").is_err());
+}
+
+#[test]
+fn owned_params() {
+    use runtime_fmt::{FormatBuf, Param};
+
+    let params: Vec<_> = (1..4).map(|i| Param::owned(i * 10)).collect();
+    let buf = FormatBuf::new("{2}-{1}-{0}", &params).unwrap();
+    assert_eq!(buf.format(), "30-20-10");
+
+    let params = [Param::owned(String::from("pad")), Param::owned(6u8)];
+    assert_eq!(FormatBuf::new("[{:>1$}]", &params).unwrap().format(), "[   pad]");
+}
+
+#[test]
+fn parse_spec() {
+    use runtime_fmt::parse_spec;
+    use runtime_fmt::spec::*;
+
+    let pieces = parse_spec("a{{b {} {name:_^+#08.2$x} c").unwrap();
+    assert_eq!(pieces.len(), 5);
+    match pieces[0] { FormatPiece::Literal(ref s) => assert_eq!(s, "a{b "), ref p => panic!("{:?}", p) }
+    match pieces[1] {
+        FormatPiece::Argument(ArgSpec { position: Position::Index(0), fill: None,
+            align: Alignment::Unknown, width: Count::Implied, precision: Count::Implied, ref ty, .. })
+            => assert_eq!(ty, ""),
+        ref p => panic!("{:?}", p),
+    }
+    match pieces[3] {
+        FormatPiece::Argument(ref arg) => {
+            match arg.position { Position::Name(ref n) => assert_eq!(n, "name"), ref p => panic!("{:?}", p) }
+            assert_eq!(arg.fill, Some('_'));
+            match arg.align { Alignment::Center => {}, ref a => panic!("{:?}", a) }
+            assert!(arg.sign_plus && arg.alternate && arg.zero_pad && !arg.sign_minus);
+            match (&arg.width, &arg.precision) {
+                (&Count::Is(8), &Count::Param(2)) => {}
+                counts => panic!("{:?}", counts),
+            }
+            assert_eq!(arg.ty, "x");
+        }
+        ref p => panic!("{:?}", p),
+    }
+    match pieces[4] { FormatPiece::Literal(ref s) => assert_eq!(s, " c"), ref p => panic!("{:?}", p) }
+
+    match parse_spec("{:.*} {w:>w$}").unwrap()[..] {
+        [FormatPiece::Argument(ArgSpec { position: Position::Index(1), precision: Count::Param(0), .. }),
+         FormatPiece::Literal(_),
+         FormatPiece::Argument(ArgSpec { width: Count::Name(ref w), .. })] => assert_eq!(w, "w"),
+        ref pieces => panic!("{:?}", pieces),
+    }
+    assert!(parse_spec("{").is_err());
+}
+
+#[test]
+fn validate_braces() {
+    use runtime_fmt::validate_braces;
+
+    const GREETING: &str = "{{{name}}} has {count:>5} item{s}";
+    const BALANCED: bool = validate_braces(GREETING);
+    assert!(BALANCED);
+    for spec in &["", "plain", "{}", "{{", "}}", "{{}}", "{0:}}}", "{:plural(1#one|other#many)}"] {
+        assert!(validate_braces(spec), "{}", spec);
+    }
+    for spec in &["{", "}", "{{}", "{}}", "{a{b}}", "x}y", "{:>5"] {
+        assert!(!validate_braces(spec), "{}", spec);
+    }
+
+    let name = "list";
+    assert_eq!(rt_format_checked!(GREETING, count = 3, s = "s"; name).unwrap(),
+               "{list} has     3 items");
+    assert_eq!(rt_format_checked!("{{}}").unwrap(), "{}");
+    // only the braces are checked ahead of time
+    assert!(rt_format_checked!("{missing}", 1).is_err());
+}
+
+#[test]
+fn compare_specs() {
+    use std::collections::HashSet;
+    use runtime_fmt::parse_spec;
+    use runtime_fmt::spec::FormatPiece;
+
+    // literal text aside, translations should keep their arguments intact
+    fn arguments(spec: &str) -> Vec<FormatPiece> {
+        parse_spec(spec).unwrap().into_iter()
+            .filter(|p| match *p { FormatPiece::Argument(_) => true, _ => false })
+            .collect()
+    }
+    assert_eq!(arguments("{name} has {count:>3} items"), arguments("{name} a {count:>3} objets"));
+    assert!(arguments("{name} has {count} items") != arguments("{count} items for {name}"));
+    assert!(arguments("{:x}") != arguments("{:X}"));
+
+    let templates: HashSet<_> = ["{0} {1}", "{} {}", "{1} {0}", "{0} {1}"].iter()
+        .map(|spec| parse_spec(spec).unwrap())
+        .collect();
+    assert_eq!(templates.len(), 2);
+    let cloned = parse_spec("a{:5}").unwrap().clone();
+    assert_eq!(cloned, parse_spec("a{0:5}").unwrap());
+}
+
+#[test]
+fn params_builder() {
+    use runtime_fmt::{FormatBuf, Param, Params};
+
+    let (name, width) = ("cell", 8);
+    let mut params = Params::new();
+    params.add(&name).add_named("w", &width).push(Param::owned(3.5));
+    assert_eq!(params.len(), 3);
+    assert_eq!(FormatBuf::new("[{:^w$}] {2}", &params).unwrap().format(), "[  cell  ] 3.5");
+    assert_eq!(FormatBuf::new("{w}", params.as_slice()).unwrap().format(), "8");
+}
+
+#[test]
+fn lazy_params() {
+    use std::cell::RefCell;
+    use runtime_fmt::{FormatBuf, Param, Params};
+    use runtime_fmt::Error::{BadName, MissingArgument};
+
+    let fields = [10, 20, 30, 40, 50];
+    let names = ["", "b", "c", "", "e"];
+    let calls = RefCell::new(Vec::new());
+    let params = Params::lazy("{0} {c} {3:>e$}", &names, |idx| {
+        calls.borrow_mut().push(idx);
+        Param::normal(&fields[idx])
+    }).unwrap();
+    assert_eq!(*calls.borrow(), [0, 2, 3, 4]);
+    assert_eq!(params.len(), 5);
+    let buf = FormatBuf::new("{0} {c} {3:>e$}", &params).unwrap();
+    assert_eq!(buf.format(), format!("10 30 {:>50}", 40));
+
+    match Params::lazy("{5}", &names, |idx| Param::normal(&fields[idx])) {
+        Err(MissingArgument { index: 5, supplied: 5 }) => {}
+        other => panic!("{:?}", other.map(|p| p.len())),
+    }
+    match Params::lazy("{d}", &names, |idx| Param::normal(&fields[idx])) {
+        Err(BadName(ref n)) if n == "d" => {}
+        other => panic!("{:?}", other.map(|p| p.len())),
+    }
+}
+
+#[test]
+fn custom_format() {
+    use std::fmt;
+    use runtime_fmt::custom::CustomFormat;
+
+    struct Color(u8, u8, u8);
+    impl Color {
+        fn hex(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.pad(&format!("#{:02x}{:02x}{:02x}", self.0, self.1, self.2))
+        }
+        fn rgb(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "rgb({}, {}, {})", self.0, self.1, self.2)
+        }
+    }
+    impl CustomFormat for Color {
+        fn custom_format(name: &str) -> Option<fn(&Self, &mut fmt::Formatter) -> fmt::Result> {
+            match name {
+                "hex" => Some(Color::hex),
+                "rgb" => Some(Color::rgb),
+                _ => None,
+            }
+        }
+    }
+
+    let teal = Color(0, 128, 128);
+    t!("#008080 rgb(0, 128, 128)"; "{0:hex} {0:rgb}", teal);
+    t!("[ #008080 ]"; "[{:^9hex}]", teal);
+    match rt_format!("{:hsl}", teal) {
+        Err(runtime_fmt::Error::NoSuchFormat(ref c)) => assert_eq!(c, "hsl"),
+        other => panic!("{:?}", other),
+    }
+    assert!(rt_format!("{:hex}", 5).is_err());
+}
+
+#[test]
+fn concat() {
+    use runtime_fmt::{FormatBuf, Param};
+
+    let a = [Param::normal(&1), Param::normal(&"x")];
+    let b = [Param::normal(&2.5), Param::normal(&4)];
+    let buf = |spec, params| FormatBuf::new(spec, params).unwrap();
+
+    assert_eq!(buf("<{}{}>", &a).concat(buf("[{}] {}", &b)).format(), "<1x>[2.5] 4");
+    assert_eq!(buf("{1}{0}", &a).concat(buf("{1}{0}", &b)).format(), "x142.5");
+    assert_eq!(buf("{0:>1$}|", &b).concat(buf("{0:.1$}", &b)).format(), " 2.5|2.5000");
+    assert_eq!(buf("a{}", &a).concat(buf("b", &[])).concat(buf("{}c", &b)).format(), "a1b2.5c");
+    assert_eq!(buf("", &[]).concat(buf("{}", &a)).format(), "1");
+
+    let mut joined = buf("{}", &a).concat(buf(" {}", &b));
+    joined.newln();
+    assert_eq!(joined.format(), "1 2.5\n");
+    assert_eq!(joined.spec(), "{} {}\n");
+    assert_eq!(joined.arg_count(), 2);
+}
+
+#[test]
+fn count_only_names() {
+    t!("[     2.5]"; "[{value:width$}]", value = 2.5, width = 8);
+    t!("[   2.500]"; "[{value:>width$.precision$}]", value = 2.5, width = 8, precision = 3u8);
+    t!("[  2.5]"; "[{:w$}]", 2.5, w = 5);
+    assert!(rt_format!("{value:widht$}", value = 2.5, width = 8).is_err());
+}
+
+#[test]
+fn limits() {
+    use runtime_fmt::{FormatBuf, Limits, Param};
+    use runtime_fmt::Error::CountTooLarge;
+
+    let limits = Limits { max_width: 10, max_precision: 4 };
+    let params = [Param::normal(&2.5), Param::named("w", &11), Param::normal(&0)];
+    let buf = |spec| FormatBuf::new_with_limits(spec, &params, limits).map(|b| b.format());
+    assert_eq!(buf("{:10.4}").unwrap(), "    2.5000");
+    match buf("{:999999999}") {
+        Err(CountTooLarge { value: 999999999, limit: 10 }) => {}
+        other => panic!("{:?}", other),
+    }
+    match buf("{:w$}") {
+        Err(CountTooLarge { value: 11, limit: 10 }) => {}
+        other => panic!("{:?}", other),
+    }
+    match buf("{:.1$}") {
+        Err(CountTooLarge { value: 11, limit: 4 }) => {}
+        other => panic!("{:?}", other),
+    }
+    match buf("{2:w$plural(0#none|other#some)}") {
+        Err(CountTooLarge { value: 11, limit: 10 }) => {}
+        other => panic!("{:?}", other),
+    }
+    assert_eq!(buf("{:.w$}").unwrap_err().to_string(), "count 11 exceeds the limit of 4");
+}
+
+#[test]
+fn write_io_buffered() {
+    use std::io;
+    use runtime_fmt::{FormatBuf, Param};
+
+    struct Counting(Vec<u8>, usize);
+    impl io::Write for Counting {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.1 += 1;
+            self.0.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> io::Result<()> { Ok(()) }
+    }
+
+    let params = [Param::normal(&1), Param::normal(&"two"), Param::normal(&3.5)];
+    let buf = FormatBuf::new("a {} b {:>5} c {} d", &params).unwrap();
+
+    let mut streamed = Counting(Vec::new(), 0);
+    buf.write_io(&mut streamed).unwrap();
+    let mut buffered = Counting(Vec::new(), 0);
+    buf.write_io_buffered(&mut buffered).unwrap();
+    assert_eq!(buffered.0, streamed.0);
+    assert_eq!(buffered.0, b"a 1 b   two c 3.5 d");
+    assert_eq!(buffered.1, 1);
+    assert!(streamed.1 > 1);
+}
+
+#[test]
+fn write_chunked() {
+    use std::io;
+    use runtime_fmt::{FormatBuf, Param};
+
+    struct Chunks(Vec<Vec<u8>>);
+    impl io::Write for Chunks {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.push(buf.to_vec());
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> io::Result<()> { Ok(()) }
+    }
+
+    let long = "x".repeat(10);
+    let params = [Param::normal(&long), Param::normal(&"é")];
+    let buf = FormatBuf::new("[{}] {}!", &params).unwrap();
+
+    let mut chunks = Chunks(Vec::new());
+    buf.write_chunked(&mut chunks, 4).unwrap();
+    let sizes: Vec<_> = chunks.0.iter().map(Vec::len).collect();
+    assert_eq!(sizes, [4, 4, 4, 4]);
+    assert_eq!(chunks.0.concat(), "[xxxxxxxxxx] é!".as_bytes());
+
+    let mut chunks = Chunks(Vec::new());
+    buf.write_chunked(&mut chunks, 100).unwrap();
+    assert_eq!(chunks.0, [b"[xxxxxxxxxx] \xc3\xa9!".to_vec()]);
+
+    let mut full = [0; 6];
+    let err = buf.write_chunked(&mut &mut full[..], 4).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::WriteZero);
+    assert_eq!(&full, b"[xxxxx");
+}
+
+#[test]
+fn names_share_indices() {
+    use runtime_fmt::{FormatBuf, Param};
+
+    let params = [Param::named("first", &"a"), Param::normal(&2)];
+    let buf = FormatBuf::new("{0} {first} {1} {first:?}", &params).unwrap();
+    assert_eq!(buf.format(), "a a 2 \"a\"");
+    assert_eq!(buf.arg_count(), 3);
+}
+
+#[test]
+fn new_owned() {
+    use runtime_fmt::{FormatBuf, OwnedFormatBuf, Param};
+
+    fn report(spec: &str, total: u32) -> OwnedFormatBuf {
+        let label = String::from("total");
+        FormatBuf::new_owned(spec, vec![
+            Param::owned(label),
+            Param::owned(total),
+            Param::owned(6usize),
+        ]).unwrap()
+    }
+
+    let mut buf = report("{0}: {1:>2$} ({1:x})", 255);
+    assert_eq!(buf.format(), "total:    255 (ff)");
+    assert_eq!(buf.arg_count(), 4);
+    buf.newln();
+    assert_eq!(buf.to_string(), "total:    255 (ff)\n");
+
+    match FormatBuf::new_owned("{3}", vec![Param::owned(1)]) {
+        Err(runtime_fmt::Error::MissingArgument { index: 3, supplied: 1 }) => {}
+        other => panic!("{:?}", other.map(|b| b.format())),
+    }
+}
+
+#[test]
+fn ignore_case() {
+    use runtime_fmt::{FormatBuf, Param};
+    use runtime_fmt::Error::{AmbiguousName, BadName};
+
+    let params = [Param::named("name", &"Ada"), Param::named("Width", &6), Param::named("name", &"x")];
+    let buf = |spec| FormatBuf::new_ignore_case(spec, &params).map(|b| b.format());
+    assert_eq!(buf("{Name}|{NAME:>width$}|{name}").unwrap(), "Ada|   Ada|Ada");
+    match buf("{nmae}") {
+        Err(BadName(ref n)) => assert_eq!(n, "nmae"),
+        other => panic!("{:?}", other),
+    }
+    assert!(FormatBuf::new("{Name}", &params).is_err());
+
+    let params = [Param::named("name", &1), Param::named("Name", &2)];
+    match FormatBuf::new_ignore_case("{NAME}", &params) {
+        Err(AmbiguousName(ref n)) => assert_eq!(n, "NAME"),
+        other => panic!("{:?}", other),
+    }
+    match FormatBuf::new_ignore_case("{:name$}", &params) {
+        Err(ref e @ AmbiguousName(_)) => assert_eq!(e.to_string(), "ambiguous name \"name\""),
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn strict() {
+    use runtime_fmt::{FormatBuf, Param};
+    use runtime_fmt::Error::UnusedArgument;
+
+    let params = [Param::normal(&1), Param::named("width", &6), Param::named("count", &2)];
+    let strict = |spec| FormatBuf::new_strict(spec, &params).map(|b| b.format());
+    assert_eq!(strict("{:width$} {count}").unwrap(), "     1 2");
+    assert_eq!(strict("{width} {2}").unwrap(), "6 2");
+    assert_eq!(strict("{count:plural(2#two|other#many)} {width}").unwrap(), "two 6");
+    match strict("{:width$} {cuont}") {
+        Err(runtime_fmt::Error::BadName(_)) => {}
+        other => panic!("{:?}", other),
+    }
+    match strict("{} {width}") {
+        Err(UnusedArgument { ref name }) => assert_eq!(name, "count"),
+        other => panic!("{:?}", other),
+    }
+    assert_eq!(strict("{count}").unwrap_err().to_string(), "argument \"width\" is never used");
+    assert!(FormatBuf::new("{}", &params).is_ok());
+}
+
+#[test]
+fn duplicate_names() {
+    use runtime_fmt::{FormatBuf, Param};
+    use runtime_fmt::Error::DuplicateName;
+
+    let params = [Param::named("x", &1), Param::normal(&2), Param::named("x", &3)];
+    assert_eq!(FormatBuf::new("{x} {1}", &params).unwrap().format(), "1 2");
+    match FormatBuf::new_strict("{x} {1}", &params) {
+        Err(DuplicateName(ref name)) => assert_eq!(name, "x"),
+        other => panic!("{:?}", other),
+    }
+    assert_eq!(FormatBuf::new_exhaustive("{x} {1} {2}", &params).unwrap_err().to_string(),
+               "more than one argument is named \"x\"");
+
+    let params = [Param::named("x", &1), Param::named("X", &2)];
+    assert_eq!(FormatBuf::new_strict("{x}{X}", &params).unwrap().format(), "12");
+}
+
+#[test]
+fn default_param() {
+    use runtime_fmt::{FormatBuf, Param};
+
+    let params = [Param::named("a", &1), Param::normal(&"x")];
+    let blank = Param::normal(&"");
+    let buf = FormatBuf::new_with_default("{a}|{b}|{1}|{c:>3}|{b?!}", &params, &blank).unwrap();
+    assert_eq!(buf.format(), "1||x|   |");
+    assert_eq!(buf.arg_count(), 5);
+
+    let width = Param::normal(&4);
+    let buf = FormatBuf::new_with_default("[{a:w$}][{b:>width$}]", &params, &width).unwrap();
+    assert_eq!(buf.format(), "[   1][   4]");
+    match FormatBuf::new_with_default("{:w$}", &params, &blank) {
+        Err(runtime_fmt::Error::Defaulted { ref name, ref source }) => {
+            assert_eq!(name, "w");
+            match **source {
+                runtime_fmt::Error::BadCount { idx: 0, ty: Some("&str"), .. } => {}
+                ref other => panic!("{:?}", other),
+            }
+        }
+        other => panic!("{:?}", other),
+    }
+    match FormatBuf::new_with_default("{2}", &params, &blank) {
+        Err(runtime_fmt::Error::MissingArgument { index: 2, supplied: 2 }) => {}
+        other => panic!("{:?}", other),
+    }
+    match FormatBuf::new_with_default("{b}{missing:x}", &params, &blank) {
+        Err(runtime_fmt::Error::Defaulted { ref name, ref source }) => {
+            assert_eq!(name, "missing");
+            match **source {
+                runtime_fmt::Error::UnsatisfiedFormat { idx: 0, .. } => {}
+                ref other => panic!("{:?}", other),
+            }
+        }
+        other => panic!("{:?}", other),
+    }
+
+    // each missing name has an argument of its own
+    let buf = FormatBuf::new_with_default("{b}{c}{b}", &params, &blank).unwrap();
+    assert_eq!(buf.arg_count(), 2);
+}
+
+#[test]
+fn format_strict() {
+    use runtime_fmt::Error::{UnusedArgument, UnusedIndex};
+
+    let (x, y) = (1, 2);
+    assert_eq!(rt_format_strict!("{} {1}", 'a', 'b').unwrap(), "a b");
+    assert_eq!(rt_format_strict!("{x}{y}"; x, y).unwrap(), "12");
+    assert_eq!(rt_format_strict!("{{}}").unwrap(), "{}");
+    match rt_format_strict!("{1}", 'a', 'b') {
+        Err(UnusedIndex(0)) => {}
+        other => panic!("{:?}", other),
+    }
+    match rt_format_strict!("{}", 'a', x = 3) {
+        Err(UnusedArgument { ref name }) => assert_eq!(name, "x"),
+        other => panic!("{:?}", other),
+    }
+    match rt_format_strict!("{x}"; x, y) {
+        Err(UnusedArgument { ref name }) => assert_eq!(name, "y"),
+        other => panic!("{:?}", other),
+    }
+    assert_eq!(rt_format_strict!("", 5).unwrap_err().to_string(), "argument 0 is never used");
+    assert_eq!(rt_format!("{1}", 'a', 'b').unwrap(), "b");
+}
+
+#[test]
+fn write_io_exact() {
+    use std::io::{Cursor, ErrorKind, Write};
+    use runtime_fmt::{DoesNotFit, FormatBuf, Param};
+
+    let params = [Param::normal(&"abc"), Param::normal(&12345)];
+    let field = |spec| FormatBuf::new(spec, &params).unwrap();
+
+    let mut record = [b'.'; 10];
+    let mut cursor = Cursor::new(&mut record[..]);
+    field("{}|").write_io_exact(&mut cursor).unwrap();
+    field("{1}").write_io_exact(&mut cursor).unwrap();
+    let err = field("{1}").write_io_exact(&mut cursor).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Other);
+    let fit = err.get_ref().and_then(|e| e.downcast_ref::<DoesNotFit>());
+    assert_eq!(fit, Some(&DoesNotFit { len: 5, remaining: 1 }));
+    assert_eq!(cursor.position(), 9);
+    field("!").write_io_exact(&mut cursor).unwrap();
+
+    // a cursor's own short write is another matter
+    let err = cursor.write_all(b"?").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::WriteZero);
+    assert!(err.get_ref().and_then(|e| e.downcast_ref::<DoesNotFit>()).is_none());
+    assert_eq!(&record, b"abc|12345!");
+}
+
+#[test]
+fn format_to() {
+    let mut report = String::from("> ");
+    rt_format_to!(report, "{} + {x}", 1, x = 2).unwrap();
+    rt_format_to!(&mut report, " = {:>3}", 3).unwrap();
+    assert_eq!(report, "> 1 + 2 =   3");
+    assert!(rt_format_to!(report, "{y}", x = 2).is_err());
+    assert_eq!(report, "> 1 + 2 =   3");
+}
+
+#[test]
+fn captured_names() {
+    use std::fmt::Write;
+
+    let name = "world";
+    let count = 3;
+    let width = 5;
+    assert_eq!(rt_format!("Hello, {name}!"; name).unwrap(), "Hello, world!");
+    assert_eq!(rt_format!("{}: {name} x{count:>width$}", 1; name, count, width).unwrap(),
+               "1: world x    3");
+    // explicit arguments come first, and unused captures are ignored
+    assert_eq!(rt_format!("{name} {count}", name = "you"; name, count, width,).unwrap(),
+               "you 3");
+    assert_eq!(rt_format!("{0} {count}", 7, count = 8; count).unwrap(), "7 8");
+
+    let mut out = String::new();
+    rt_write!(out, "[{name}]"; name).unwrap();
+    rt_format_to!(out, "{}{count}", '-'; count).unwrap();
+    assert_eq!(out, "[world]-3");
+    match rt_format!("{missing}"; name) {
+        Err(runtime_fmt::Error::BadName(ref n)) => assert_eq!(n, "missing"),
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn right_to_left() {
+    use runtime_fmt::{Direction, FormatBuf, Param};
+
+    let params = [Param::normal(&"שלום"), Param::normal(&42), Param::normal(&1)];
+    let rtl = |spec| FormatBuf::new_with_direction(spec, &params, Direction::RightToLeft)
+        .unwrap()
+        .format();
+    assert_eq!(rtl("[{0:<6}]"), "[  שלום]");
+    assert_eq!(rtl("[{0:>6}]"), "[שלום  ]");
+    assert_eq!(rtl("[{0:^6}] [{1:6}] [{0:6}]"), "[ שלום ] [    42] [שלום  ]");
+    assert_eq!(rtl("[{1:*<5}] [{2:<5plural(1#א|other#ב)}]"), "[***42] [    א]");
+
+    let ltr = FormatBuf::new_with_direction("[{0:<6}]", &params, Direction::default()).unwrap();
+    assert_eq!(ltr.format(), "[שלום  ]");
+}
+
+#[test]
+fn interceptor() {
+    use runtime_fmt::{FormatBuf, Param};
+
+    let params = [Param::normal(&"alice"), Param::normal(&"hunter2"), Param::normal(&7),
+                  Param::normal(&3)];
+    let buf = FormatBuf::new("{0} [{1:>9}] {0:?} {2:3$}", &params).unwrap();
+    let mut seen = Vec::new();
+    let logged = buf.with_interceptor(|idx, text: &mut String| {
+        seen.push((idx, text.clone()));
+        if idx == 1 {
+            *text = "***".into();
+        }
+    }, |args: std::fmt::Arguments| args.to_string());
+    assert_eq!(logged, "alice [***] \"alice\"   7");
+    assert_eq!(seen, [
+        (0, "alice".into()),
+        (1, "  hunter2".into()),
+        (0, "\"alice\"".into()),
+        (2, "  7".into()),
+    ]);
+    assert_eq!(buf.with_interceptor(|_, _| {}, |args: std::fmt::Arguments| args.to_string()),
+               "alice [  hunter2] \"alice\"   7");
+}
+
+// Writes part of its output and then fails, as a formatting trait might.
+struct Broken;
+
+impl std::fmt::Display for Broken {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("partial")?;
+        Err(std::fmt::Error)
+    }
+}
+
+// A destination which refuses every write.
+struct FullFmt;
+
+impl std::fmt::Write for FullFmt {
+    fn write_str(&mut self, _: &str) -> std::fmt::Result {
+        Err(std::fmt::Error)
+    }
+}
+
+#[test]
+fn format_lossy() {
+    use runtime_fmt::{FormatBuf, Param};
+    use std::fmt;
+
+    let params = [Param::normal(&1), Param::normal(&Broken), Param::normal(&"end")];
+    let buf = FormatBuf::new("{} {} {:>5} {1}", &params).unwrap();
+    let (text, failures) = buf.format_lossy();
+    assert_eq!(text, "1 <format error>   end <format error>");
+    assert_eq!(failures, [(1, fmt::Error), (1, fmt::Error)]);
+
+    let fine = FormatBuf::new("{0}-{2}", &params).unwrap();
+    assert_eq!(fine.format_lossy(), ("1-end".into(), vec![]));
+}
+
+#[test]
+fn write_fmt_atomic() {
+    use runtime_fmt::{FormatBuf, Param};
+    use std::fmt;
+
+    let params = [Param::normal(&"ok"), Param::normal(&Broken)];
+    let mut out = String::from("> ");
+    assert_eq!(FormatBuf::new("[{}]", &params).unwrap().write_fmt_atomic(&mut out), Ok(()));
+    assert_eq!(out, "> [ok]");
+
+    let buf = FormatBuf::new("{} {} {0}", &params).unwrap();
+    assert_eq!(buf.write_fmt_atomic(&mut out), Err((10, fmt::Error)));
+    assert_eq!(out, "> [ok]");
+    assert!(buf.write_fmt(&mut out).is_err());
+    assert_eq!(out, "> [ok]ok partial");
+
+    let buf = FormatBuf::new("{}!", &params).unwrap();
+    assert_eq!(buf.write_fmt_atomic(&mut FullFmt), Err((3, fmt::Error)));
+}
+
+#[test]
+fn append() {
+    use runtime_fmt::{FormatBuf, Param};
+
+    let params = [Param::normal(&"GET"), Param::normal(&"/")];
+    let mut request = FormatBuf::new("{} {}", &params).unwrap();
+    request.append(" HTTP/1.1").append("\r\n");
+    assert_eq!(request.format(), "GET / HTTP/1.1\r\n");
+    assert_eq!(request.spec(), "{} {} HTTP/1.1\r\n");
+
+    let mut framed = FormatBuf::new("{0:>4}", &params).unwrap();
+    framed.append("{}").newln().append("");
+    assert_eq!(framed.format(), " GET{}\n");
+    assert_eq!(framed.spec(), "{0:>4}{{}}\n");
+
+    let mut owned = FormatBuf::new_owned("{}", vec![Param::owned(3)]).unwrap();
+    owned.append("\u{1e}");
+    assert_eq!(owned.format(), "3\u{1e}");
+}
+
+#[test]
+fn dotted_params() {
+    use runtime_fmt::{FormatBuf, Param};
+    use runtime_fmt::spec::{FormatPiece, Position};
+
+    let params = [Param::named("user.name", &"ann"), Param::named("user.width", &5usize)];
+    let buf = FormatBuf::new("[{user.name:>user.width$}] {user.name}.", &params).unwrap();
+    assert_eq!(buf.format(), "[  ann] ann.");
+
+    match runtime_fmt::parse_spec("{a.b.c}").unwrap()[0] {
+        FormatPiece::Argument(ref arg) => assert_eq!(arg.position, Position::Name("a.b.c".into())),
+        ref other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn reparse() {
+    use runtime_fmt::{Error, FormatBuf, Param};
+
+    let records = [("a", 1), ("bb", 22), ("ccc", 333)];
+    let params: Vec<_> = records.iter()
+        .map(|r| [Param::named("name", &r.0), Param::named("count", &r.1)])
+        .collect();
+    let spec = "{name:>4}={count:<4}|";
+    let mut buf = FormatBuf::new("", &[]).unwrap();
+    let mut out = String::new();
+    for params in &params {
+        buf.reparse(spec, params).unwrap();
+        out.push_str(&buf.format());
+    }
+    assert_eq!(out, "   a=1   |  bb=22  | ccc=333 |");
+
+    buf.reparse("{count} {}", &params[0]).unwrap();
+    assert_eq!(buf.format(), "1 a");
+    assert_eq!(buf.spec(), "{count} {}");
+    buf.reset();
+    assert_eq!(buf.format(), "");
+    assert_eq!(buf.arg_count(), 0);
+
+    match buf.reparse("{missing}", &params[1]) {
+        Err(Error::BadName(ref n)) => assert_eq!(n, "missing"),
+        other => panic!("{:?}", other),
+    }
+    assert_eq!(buf.format(), "");
+    buf.reparse("{count:?}", &params[2]).unwrap();
+    assert_eq!(buf.to_string(), "333");
+}
+
+#[test]
+fn format_utf16() {
+    use runtime_fmt::{FormatBuf, Param};
+
+    let params = [Param::normal(&"naïve"), Param::normal(&'𝄞'), Param::normal(&12)];
+    let buf = FormatBuf::new("{0:>7}|{1}|{2:#x}", &params).unwrap();
+    let expected: Vec<u16> = buf.format().encode_utf16().collect();
+    assert_eq!(buf.format_utf16(), expected);
+    assert_eq!(String::from_utf16(&buf.format_utf16()).unwrap(), "  naïve|𝄞|0xc");
+}
@@ -0,0 +1,36 @@
+#![cfg(feature = "json")]
+
+extern crate runtime_fmt;
+
+use runtime_fmt::spec::to_json;
+use runtime_fmt::Error;
+
+#[test]
+fn literals() {
+    assert_eq!(to_json("").unwrap(), "[]");
+    assert_eq!(to_json("a {{\"b\"}}\n").unwrap(), r#"[{"literal": "a {\"b\"}\n"}]"#);
+    assert_eq!(to_json("\\\t\u{1}").unwrap(), r#"[{"literal": "\\\t\u0001"}]"#);
+}
+
+#[test]
+fn arguments() {
+    assert_eq!(to_json("x={}").unwrap(), concat!(
+        r#"[{"literal": "x="}, {"arg": {"name": null, "index": 0, "spec": {"fill": null, "#,
+        r#""align": null, "flags": [], "width": null, "precision": null, "type": ""}, "#,
+        r#""prefix": null}}]"#));
+    assert_eq!(to_json("{count:*>+#08.3x}").unwrap(), concat!(
+        r#"[{"arg": {"name": "count", "index": null, "spec": {"fill": "*", "align": "right", "#,
+        r##""flags": ["+", "#", "0"], "width": 8, "precision": 3, "type": "x"}, "##,
+        r#""prefix": null}}]"#));
+    assert_eq!(to_json("{:^w$.*}").unwrap(), concat!(
+        r#"[{"arg": {"name": null, "index": 1, "spec": {"fill": null, "align": "center", "#,
+        r#""flags": [], "width": {"name": "w"}, "precision": {"index": 0}, "type": ""}, "#,
+        r#""prefix": null}}]"#));
+    assert_eq!(to_json("{tel?Tel: }").unwrap(), concat!(
+        r#"[{"arg": {"name": "tel", "index": null, "spec": {"fill": null, "align": null, "#,
+        r#""flags": [], "width": null, "precision": null, "type": ""}, "prefix": "Tel: "}}]"#));
+    match to_json("{") {
+        Err(Error::BadSyntax(_)) => {}
+        other => panic!("{:?}", other),
+    }
+}
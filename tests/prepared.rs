@@ -0,0 +1,772 @@
+#[macro_use] extern crate runtime_fmt_derive;
+extern crate runtime_fmt;
+
+use runtime_fmt::PreparedFormat;
+use runtime_fmt::Error::*;
+
+#[derive(FormatArgs)]
+struct Record {
+    name: &'static str,
+    count: usize,
+    ratio: f64,
+}
+
+#[derive(FormatArgs)]
+struct Pair(i32, &'static str);
+
+#[test]
+fn validate() {
+    assert!(PreparedFormat::<Record>::validate("{name}: {count} ({ratio:.2})").is_ok());
+    assert!(PreparedFormat::<Record>::validate("{name:>count$}").is_ok());
+    assert!(PreparedFormat::<Pair>::validate("{1} {0:x}").is_ok());
+
+    match PreparedFormat::<Record>::validate("{nmae}") {
+        Err(BadName(ref n)) => assert_eq!(n, "nmae"),
+        other => panic!("{:?}", other),
+    }
+    match PreparedFormat::<Pair>::validate("{1:x}") {
+        Err(UnsatisfiedFormat { idx: 1, .. }) => {}
+        other => panic!("{:?}", other),
+    }
+    match PreparedFormat::<Record>::validate("{name:ratio$}") {
+        Err(BadCount { idx: 2, .. }) => {}
+        other => panic!("{:?}", other),
+    }
+    match PreparedFormat::<Pair>::validate("{2}") {
+        Err(BadIndex(2)) => {}
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn with_buf() {
+    let records = [
+        Record { name: "a", count: 1, ratio: 0.5 },
+        Record { name: "b", count: 2, ratio: 0.25 },
+    ];
+    let mut prepared = PreparedFormat::<Record>::prepare("{name}={count} ({ratio})").unwrap();
+    prepared.newln();
+
+    let mut buf = Vec::new();
+    let mut out = String::new();
+    for record in &records {
+        out.push_str(&prepared.with_buf(record, &mut buf, std::fmt::format));
+    }
+    assert_eq!(out, "a=1 (0.5)\nb=2 (0.25)\n");
+}
+
+#[test]
+fn format_into() {
+    let prepared = PreparedFormat::<Pair>::prepare("[{1}:{0}]").unwrap();
+    let mut out = String::with_capacity(32);
+    prepared.format_into(&Pair(1, "x"), &mut out);
+    prepared.format_into(&Pair(2, "y"), &mut out);
+    assert_eq!(out, "[x:1][y:2]");
+}
+
+#[test]
+fn formatted_len() {
+    let prepared = PreparedFormat::<Pair>::prepare("[{1}:{0}]").unwrap();
+    assert_eq!(prepared.formatted_len(&Pair(1, "x")), 5);
+    assert_eq!(prepared.formatted_len(&Pair(-100, "\u{e9}")), 9);
+}
+
+#[test]
+fn format_with_capacity() {
+    let prepared = PreparedFormat::<Pair>::prepare("{1}: {0:>10}").unwrap();
+    let formatted = prepared.format_with_capacity(&Pair(7, "seven"), 100);
+    assert_eq!(formatted, "seven:          7");
+    assert!(formatted.capacity() >= 100);
+    assert_eq!(prepared.format_with_capacity(&Pair(1, "one"), 0), "one:          1");
+}
+
+#[test]
+fn display() {
+    let prepared = PreparedFormat::<Pair>::prepare("{1}={0}").unwrap();
+    let pair = Pair(5, "five");
+    assert_eq!(format!("<{}> <{:?}>", prepared.display(&pair), prepared.display(&pair)),
+               "<five=5> <five=5>");
+}
+
+#[test]
+fn derive_positional() {
+    let record = Record { name: "widget", count: 3, ratio: 1.5 };
+    let prepared = PreparedFormat::<Record>::prepare("{0} x{1} @{2} = {name:>1$}").unwrap();
+    assert_eq!(prepared.format(&record), "widget x3 @1.5 = widget");
+    match PreparedFormat::<Record>::validate("{3}") {
+        Err(BadIndex(3)) => {}
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn cache() {
+    use std::sync::Arc;
+    use runtime_fmt::cache;
+
+    let first = cache::prepared::<Pair>("{1}={0}").unwrap();
+    let second = cache::prepared::<Pair>(&String::from("{1}={0}")).unwrap();
+    assert!(Arc::ptr_eq(&first, &second));
+    assert_eq!(second.format(&Pair(5, "five")), "five=5");
+
+    // The same format string against another type is a separate entry.
+    let record = cache::prepared::<Record>("{1}={0}").unwrap();
+    assert_eq!(record.format(&Record { name: "n", count: 2, ratio: 0.0 }), "2=n");
+
+    cache::set_capacity(1);
+    let evicted = cache::prepared::<Pair>("{0}").unwrap();
+    assert!(!Arc::ptr_eq(&first, &cache::prepared::<Pair>("{1}={0}").unwrap()));
+    assert!(!Arc::ptr_eq(&evicted, &cache::prepared::<Pair>("{0}").unwrap()));
+
+    cache::clear();
+    assert!(cache::prepared::<Pair>("{2}").is_err());
+}
+
+#[test]
+fn clone() {
+    let prepared = PreparedFormat::<Pair>::prepare("{0}: {1}").unwrap();
+    let mut line = prepared.clone();
+    line.newln();
+    assert_eq!(prepared.format(&Pair(1, "a")), "1: a");
+    assert_eq!(line.format(&Pair(1, "a")), "1: a\n");
+
+    let mut copy = PreparedFormat::<Pair>::prepare("{1}").unwrap();
+    copy.clone_from(&line);
+    assert_eq!(copy.format(&Pair(2, "b")), "2: b\n");
+}
+
+#[test]
+fn send_sync() {
+    use std::rc::Rc;
+    use std::sync::{Arc, OnceLock};
+    use std::thread;
+
+    fn assert_send_sync<T: Send + Sync>(_: &T) {}
+
+    // Even a type which is neither `Send` nor `Sync` may be prepared against.
+    #[derive(FormatArgs)]
+    struct Local(Rc<i32>);
+    assert_send_sync(&PreparedFormat::<Local>::prepare("{0}").unwrap());
+
+    // A `static` must be `Sync`, which `OnceLock` only is for a value which
+    // is `Send` and `Sync` itself.
+    static SHARED: OnceLock<PreparedFormat<'static, Pair>> = OnceLock::new();
+    let shared = SHARED.get_or_init(|| PreparedFormat::prepare("{1}#{0}").unwrap());
+
+    let arc = Arc::new(shared.clone());
+    let handles: Vec<_> = (0..4).map(|i| {
+        let arc = arc.clone();
+        thread::spawn(move || (shared.format(&Pair(i, "s")), arc.format(&Pair(i, "a"))))
+    }).collect();
+    for (i, handle) in handles.into_iter().enumerate() {
+        assert_eq!(handle.join().unwrap(), (format!("s#{}", i), format!("a#{}", i)));
+    }
+}
+
+#[test]
+fn referenced() {
+    let prepared = PreparedFormat::<Record>::prepare("{name:>count$} {0} {name}").unwrap();
+    assert_eq!(prepared.referenced_names().collect::<Vec<_>>(), ["name", "count"]);
+    assert_eq!(prepared.referenced_indices(), [0, 1]);
+
+    let owned = prepared.clone().into_owned();
+    assert_eq!(owned.referenced_names().collect::<Vec<_>>(), ["name", "count"]);
+
+    #[derive(FormatArgs)]
+    struct Width(usize, f64);
+    let prepared = PreparedFormat::<Width>::prepare("{1:.0$} {1}").unwrap();
+    assert_eq!(prepared.referenced_names().count(), 0);
+    assert_eq!(prepared.referenced_indices(), [0, 1]);
+}
+
+#[test]
+fn grouped() {
+    let prepared = PreparedFormat::<Record>::prepare("{count:grp}").unwrap();
+    let record = Record { name: "r", count: 1234567, ratio: 0.5 };
+    assert_eq!(prepared.format(&record), "1,234,567");
+    match PreparedFormat::<Record>::validate("{ratio:grp}") {
+        Err(UnsatisfiedFormat { idx: 2, must_implement: "Grouped" }) => {}
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn choices() {
+    let spec = "{count} {count:plural(1#file|files)}";
+    let prepared = PreparedFormat::<Record>::prepare(spec).unwrap();
+    for &(count, out) in &[(0, "0 files"), (1, "1 file"), (12, "12 files")] {
+        assert_eq!(prepared.format(&Record { name: "r", count: count, ratio: 0.5 }), out);
+    }
+    let spec = "[{count:>5plural(1#one|other)}]";
+    let shape = PreparedFormat::<Record>::prepare(spec).unwrap().shape();
+    let bound = PreparedFormat::<Record>::from_shape(&shape).unwrap();
+    assert_eq!(bound.format(&Record { name: "r", count: 1, ratio: 0.5 }), "[  one]");
+
+    match PreparedFormat::<Record>::validate("{name:plural(1#one|other)}") {
+        Err(UnsatisfiedFormat { idx: 0, must_implement: "Into<i128>" }) => {}
+        other => panic!("{:?}", other),
+    }
+    match PreparedFormat::<Record>::validate("{count:plural(1#one)}") {
+        Err(NoSuchFormat(ref c)) => assert_eq!(c, "plural(1#one)"),
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn shape() {
+    let record = Record { name: "bolt", count: 12, ratio: 0.25 };
+    let shape = {
+        let spec = String::from("{name:>count$}: {count:#x} {ratio:.1} {name}");
+        PreparedFormat::<Record>::prepare(&spec).unwrap().shape()
+    };
+    let bound = PreparedFormat::<Record>::from_shape(&shape).unwrap();
+    assert_eq!(bound.spec(), "{name:>count$}: {count:#x} {ratio:.1} {name}");
+    assert_eq!(bound.format(&record), "        bolt: 0xc 0.2 bolt");
+    assert_eq!(bound.referenced_names().collect::<Vec<_>>(), ["name", "count", "ratio"]);
+    assert_eq!(bound.referenced_indices(), [0, 1, 2]);
+
+    let shape = PreparedFormat::<Record>::prepare("{} {}").unwrap().shape();
+    assert_eq!(PreparedFormat::<Record>::from_shape(&shape).unwrap().format(&record), "bolt 12");
+    match PreparedFormat::<Pair>::from_shape(&PreparedFormat::<Record>::prepare("{2}").unwrap().shape()) {
+        Err(BadIndex(2)) => {}
+        other => panic!("{:?}", other.map(|_| ())),
+    }
+}
+
+#[derive(FormatArgs)]
+struct Padded {
+    value: f64,
+    width: usize,
+    precision: usize,
+}
+
+#[test]
+fn count_only_names() {
+    let padded = Padded { value: 2.5, width: 8, precision: 3 };
+    let prepared = PreparedFormat::<Padded>::prepare("[{value:width$}]").unwrap();
+    assert_eq!(prepared.format(&padded), "[     2.5]");
+    assert_eq!(prepared.referenced_names().collect::<Vec<_>>(), ["value", "width"]);
+
+    let prepared = PreparedFormat::<Padded>::prepare("[{value:>width$.precision$}]").unwrap();
+    assert_eq!(prepared.format(&padded), "[   2.500]");
+    let prepared = PreparedFormat::<Padded>::prepare("[{0:<1$.precision$}]").unwrap();
+    assert_eq!(prepared.format(&padded), "[2.500   ]");
+
+    match PreparedFormat::<Padded>::validate("{value:widht$}") {
+        Err(BadName(ref n)) => assert_eq!(n, "widht"),
+        other => panic!("{:?}", other),
+    }
+    match PreparedFormat::<Padded>::validate("{width:value$}") {
+        Err(BadCount { idx: 0, .. }) => {}
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn limits() {
+    use runtime_fmt::Limits;
+
+    let limits = Limits { max_width: 10, max_precision: 4 };
+    let prepare = |spec| PreparedFormat::<Padded>::prepare_with_limits(spec, limits);
+    match prepare("{value:11}") {
+        Err(CountTooLarge { value: 11, limit: 10 }) => {}
+        other => panic!("{:?}", other.map(|_| ())),
+    }
+    match prepare("{value:.5}") {
+        Err(CountTooLarge { value: 5, limit: 4 }) => {}
+        other => panic!("{:?}", other.map(|_| ())),
+    }
+    assert!(prepare("{value:10.4}").is_ok());
+    assert!(PreparedFormat::<Padded>::prepare("{value:11.5}").is_ok());
+
+    // counts from the value are clamped
+    let prepared = prepare("[{value:width$.precision$}]").unwrap();
+    let big = Padded { value: 2.5, width: 1000, precision: 1000 };
+    assert_eq!(prepared.format(&big), "[    2.5000]");
+    let small = Padded { value: 2.5, width: 5, precision: 1 };
+    assert_eq!(prepared.format(&small), "[  2.5]");
+
+    // a count used for both takes the lower limit
+    let prepared = prepare("[{value:width$.width$}]").unwrap();
+    assert_eq!(prepared.format(&big), "[2.5000]");
+    let shape = prepared.shape();
+    let prepared = PreparedFormat::<Padded>::from_shape(&shape).unwrap();
+    assert_eq!(prepared.format(&big), "[2.5000]");
+}
+
+#[derive(FormatArgs)]
+struct Named {
+    first: &'static str,
+    second: usize,
+}
+
+#[test]
+fn names_share_indices() {
+    let named = Named { first: "a", second: 2 };
+    let prepared = PreparedFormat::<Named>::prepare("{0} {first} {1:x} {second:x} {second}").unwrap();
+    assert_eq!(prepared.format(&named), "a a 2 2 2");
+    assert_eq!(prepared.arg_count(), 3);
+    assert_eq!(prepared.referenced_indices(), [0, 1]);
+    assert_eq!(prepared.referenced_names().collect::<Vec<_>>(), ["first", "second"]);
+
+    // a count by name and by index is likewise one argument
+    let prepared = PreparedFormat::<Named>::prepare("{0:1$}|{first:second$}|{0:>1$}").unwrap();
+    assert_eq!(prepared.format(&named), "a |a | a");
+    assert_eq!(prepared.arg_count(), 2);
+}
+
+#[derive(FormatArgs)]
+struct Renamed {
+    second: u8,
+    third: (),
+    first: String,
+}
+
+#[test]
+fn rebind() {
+    let prepared = PreparedFormat::<Named>::prepare("{first}: {second:>3} {second:x}").unwrap();
+    let rebound = prepared.rebind::<Renamed>().unwrap();
+    let renamed = Renamed { second: 26, third: (), first: "b".into() };
+    assert_eq!(rebound.format(&renamed), "b:  26 1a");
+    assert_eq!(rebound.referenced_indices(), [0, 2]);
+    assert_eq!(prepared.format(&Named { first: "a", second: 10 }), "a:  10 a");
+
+    let prepared = PreparedFormat::<Named>::prepare("{first:second$}|{1}").unwrap();
+    match prepared.rebind::<Renamed>() {
+        Err(BadCount { idx: 0, .. }) => {}
+        other => panic!("{:?}", other.map(|_| ())),
+    }
+    match prepared.rebind::<Record>() {
+        Err(BadName(ref n)) => assert_eq!(n, "first"),
+        other => panic!("{:?}", other.map(|_| ())),
+    }
+    let prepared = PreparedFormat::<Named>::prepare("{0} {1}").unwrap();
+    assert_eq!(prepared.rebind::<Pair>().unwrap().format(&Pair(3, "c")), "3 c");
+    match prepared.rebind::<Renamed>() {
+        Err(UnsatisfiedFormat { idx: 1, .. }) => {}
+        other => panic!("{:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+fn arrays() {
+    let prepared = PreparedFormat::<[f64; 3]>::prepare("{2}, {0:.1}, {1:e}").unwrap();
+    assert_eq!(prepared.format(&[1.0, 0.5, 2.25]), "2.25, 1.0, 5e-1");
+    assert_eq!(PreparedFormat::prepare("{} {}").unwrap().format(&["a", "b"]), "a b");
+    assert_eq!(PreparedFormat::prepare("[{:>1$}]").unwrap().format(&[3usize, 4]), "[   3]");
+    assert_eq!(PreparedFormat::prepare("{31}").unwrap().format(&[7u8; 32]), "7");
+    assert_eq!(PreparedFormat::<[u8; 0]>::prepare("none").unwrap().format(&[]), "none");
+
+    match PreparedFormat::<[u8; 2]>::validate("{2}") {
+        Err(BadIndex(2)) => {}
+        other => panic!("{:?}", other),
+    }
+    match PreparedFormat::<[u8; 2]>::validate("{first}") {
+        Err(BadName(ref n)) => assert_eq!(n, "first"),
+        other => panic!("{:?}", other),
+    }
+    match PreparedFormat::<[u8; 2]>::validate("{0:1$}") {
+        Err(BadCount { idx: 1, .. }) => {}
+        other => panic!("{:?}", other),
+    }
+    match PreparedFormat::<[(); 1]>::validate("{}") {
+        Err(UnsatisfiedFormat { idx: 0, .. }) => {}
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn right_to_left() {
+    use runtime_fmt::Direction;
+
+    let prepared = PreparedFormat::<Record>::prepare_with_direction(
+        "{name:<5}|{count:>4}|{ratio:^7}", Direction::RightToLeft).unwrap();
+    let record = Record { name: "ab", count: 3, ratio: 0.5 };
+    assert_eq!(prepared.format(&record), "   ab|3   |  0.5  ");
+}
+
+#[test]
+fn format_all() {
+    let records = vec![
+        Record { name: "a", count: 1, ratio: 0.5 },
+        Record { name: "b", count: 2, ratio: 0.25 },
+    ];
+    let prepared = PreparedFormat::<Record>::prepare("{name}={count:02}").unwrap();
+    assert_eq!(prepared.format_all(&records), ["a=01", "b=02"]);
+    assert_eq!(prepared.format_all(records.iter().rev()), ["b=02", "a=01"]);
+    assert!(prepared.format_all(Vec::<Record>::new()).is_empty());
+
+    let mut out = String::from("> ");
+    prepared.format_all_into(records, &mut out);
+    assert_eq!(out, "> a=01\nb=02");
+    prepared.format_all_into(&[] as &[Record], &mut out);
+    assert_eq!(out, "> a=01\nb=02");
+}
+
+#[test]
+fn try_from() {
+    use std::convert::{TryFrom, TryInto};
+    let record = Record { name: "x", count: 2, ratio: 0.5 };
+    let prepared: PreparedFormat<Record> = "{name}={count}".try_into().unwrap();
+    assert_eq!(prepared.format(&record), "x=2");
+    match PreparedFormat::<Record>::try_from("{nmae}") {
+        Err(BadName(ref n)) => assert_eq!(n, "nmae"),
+        other => panic!("{:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+fn delims() {
+    let record = Record { name: "x", count: 2, ratio: 0.5 };
+    let prepare = |spec| PreparedFormat::<Record>::prepare_with_delims(spec, '<', '>');
+    let prepared = prepare("{<name>: <count:>3>} <<<ratio>>>").unwrap();
+    assert_eq!(prepared.format(&record), "{x:   2} <0.5>");
+    assert_eq!(prepared.spec(), "{<name>: <count:>3>} <<<ratio>>>");
+    assert_eq!(prepare("{{}}").unwrap().format(&record), "{{}}");
+    assert_eq!(PreparedFormat::<Record>::prepare_with_delims("$name; {name}", '$', ';').unwrap()
+        .format(&record), "x {name}");
+    assert_eq!(PreparedFormat::<Record>::prepare_with_delims("«name»", '«', '»').unwrap()
+        .format(&record), "x");
+
+    for spec in &["<name", "name>", "<:>>"] {
+        match prepare(spec) {
+            Err(BadSyntax(_)) => {}
+            other => panic!("{:?}", other.map(|_| ())),
+        }
+    }
+    match prepare("<nmae>") {
+        Err(BadName(ref n)) => assert_eq!(n, "nmae"),
+        other => panic!("{:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+fn tuples() {
+    let prepared = PreparedFormat::<(i32, &str, f64)>::prepare("{0} {1} {2:.1}").unwrap();
+    assert_eq!(prepared.format(&(1, "two", 3.0)), "1 two 3.0");
+    assert_eq!(PreparedFormat::prepare("{}").unwrap().format(&('x',)), "x");
+    assert_eq!(PreparedFormat::prepare("[{1:>0$}]").unwrap().format(&(4usize, 'y')), "[   y]");
+    let twelve = (0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, "eleven");
+    assert_eq!(PreparedFormat::prepare("{11} {5}").unwrap().format(&twelve), "eleven 5");
+
+    match PreparedFormat::<(u8, u8)>::validate("{2}") {
+        Err(BadIndex(2)) => {}
+        other => panic!("{:?}", other),
+    }
+    match PreparedFormat::<(u8, u8)>::validate("{first}") {
+        Err(BadName(ref n)) => assert_eq!(n, "first"),
+        other => panic!("{:?}", other),
+    }
+    match PreparedFormat::<(u8, i32)>::validate("{0:1$}") {
+        Err(BadCount { idx: 1, .. }) => {}
+        other => panic!("{:?}", other),
+    }
+    match PreparedFormat::<(u8, ())>::validate("{1}") {
+        Err(UnsatisfiedFormat { idx: 1, .. }) => {}
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn interned() {
+    use std::ptr;
+    use runtime_fmt::intern::Interner;
+
+    let pool = Interner::new();
+    let record = Record { name: "n", count: 3, ratio: 0.5 };
+    let (first, second) = {
+        let a = String::from("Record: {name} x{count}");
+        let b = String::from("Record: {name} x{count}");
+        let first = PreparedFormat::<Record>::prepare_interned(&a, &pool).unwrap();
+        let second = PreparedFormat::<Record>::prepare_interned(&b, &pool).unwrap();
+        (first, second)
+    };
+    assert_eq!(first.format(&record), "Record: n x3");
+    assert!(ptr::eq(first.spec(), second.spec()));
+    // the spec, two pieces, the empty format type, and two names
+    assert_eq!(pool.len(), 6);
+
+    let third = PreparedFormat::<Record>::prepare_interned("Record: {{{ratio}}}", &pool).unwrap();
+    assert_eq!(third.format(&record), "Record: {0.5}");
+    assert!(ptr::eq(pool.intern("Record: "), pool.intern(&String::from("Record: "))));
+
+    match PreparedFormat::<Record>::prepare_interned("{nmae}", &pool) {
+        Err(BadName(ref n)) => assert_eq!(n, "nmae"),
+        other => panic!("{:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+fn interceptor() {
+    let prepared = PreparedFormat::<Record>::prepare("{name}: {count:>3} ({ratio})").unwrap();
+    let record = Record { name: "secret", count: 4, ratio: 0.5 };
+    let masked = prepared.with_interceptor(&record, |idx, text: &mut String| if idx == 0 {
+        *text = "*".repeat(text.len());
+    }, std::fmt::format);
+    assert_eq!(masked, "******:   4 (0.5)");
+}
+
+#[test]
+fn format_lossy() {
+    use std::fmt;
+
+    struct Broken;
+    impl fmt::Display for Broken {
+        fn fmt(&self, _: &mut fmt::Formatter) -> fmt::Result {
+            Err(fmt::Error)
+        }
+    }
+
+    #[derive(FormatArgs)]
+    struct Entry {
+        id: u32,
+        payload: Broken,
+    }
+
+    let prepared = PreparedFormat::<Entry>::prepare("#{id}: {payload}").unwrap();
+    let (text, failures) = prepared.format_lossy(&Entry { id: 9, payload: Broken });
+    assert_eq!(text, "#9: <format error>");
+    assert_eq!(failures, [(1, fmt::Error)]);
+}
+
+#[test]
+fn pointers() {
+    #[derive(FormatArgs)]
+    struct Pointers<'a> {
+        shared: &'a i32,
+        raw: *const i32,
+        boxed: Box<i32>,
+    }
+
+    let x = 1;
+    let t = Pointers { shared: &x, raw: &x, boxed: Box::new(2) };
+    let prepared = PreparedFormat::<Pointers>::prepare("{shared:p} {raw:p} {boxed:p}").unwrap();
+    assert_eq!(prepared.format(&t), format!("{:p} {:p} {:p}", t.shared, t.raw, t.boxed));
+}
+
+#[test]
+fn append() {
+    let mut prepared = PreparedFormat::<Pair>::prepare("{0},{1}").unwrap();
+    prepared.append("\r\n");
+    assert_eq!(prepared.format(&Pair(1, "x")), "1,x\r\n");
+    assert_eq!(prepared.spec(), "{0},{1}\r\n");
+    assert_eq!(PreparedFormat::<Pair>::prepare(prepared.spec()).unwrap().format(&Pair(2, "y")),
+               "2,y\r\n");
+}
+
+#[test]
+fn dotted_names() {
+    #[derive(FormatArgs)]
+    struct Address {
+        city: &'static str,
+        zip: u32,
+    }
+
+    #[derive(FormatArgs)]
+    struct User {
+        name: &'static str,
+        width: usize,
+        address: Address,
+    }
+
+    #[derive(FormatArgs)]
+    struct Message {
+        id: u8,
+        user: User,
+        body: &'static str,
+    }
+
+    let message = Message {
+        id: 3,
+        user: User { name: "ann", width: 6, address: Address { city: "Oslo", zip: 150 } },
+        body: "hi",
+    };
+    let prepared = PreparedFormat::<Message>::prepare(
+        "#{id} {user.name:>user.width$} from {user.address.city} ({user.address.zip:05}): {body}."
+    ).unwrap();
+    assert_eq!(prepared.format(&message), "#3    ann from Oslo (00150): hi.");
+    assert_eq!(PreparedFormat::<Message>::prepare("{user.name} {user.name:?}").unwrap()
+                   .format(&message), "ann \"ann\"");
+
+    let shape = prepared.shape();
+    assert_eq!(PreparedFormat::<Message>::from_shape(&shape).unwrap().format(&message),
+               "#3    ann from Oslo (00150): hi.");
+
+    for &(spec, name) in &[
+        ("{user.age}", "user.age"),
+        ("{body.len}", "body.len"),
+        ("{user.address.city.name}", "user.address.city.name"),
+    ] {
+        match PreparedFormat::<Message>::prepare(spec) {
+            Err(BadName(ref n)) if n == name => {}
+            other => panic!("{}: {:?}", spec, other.map(|_| ())),
+        }
+    }
+    match PreparedFormat::<Message>::prepare("{user.}") {
+        Err(BadSyntax(_)) => {}
+        other => panic!("{:?}", other.map(|_| ())),
+    }
+    match PreparedFormat::<Message>::prepare("{user.address:?}") {
+        Err(UnsatisfiedFormat { must_implement: "Debug", .. }) => {}
+        other => panic!("{:?}", other.map(|_| ())),
+    }
+    match PreparedFormat::<Message>::prepare("{:user.name$}") {
+        Err(BadCount { .. }) => {}
+        other => panic!("{:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+fn alternate_prefixes() {
+    let prepared = PreparedFormat::<Pair>::prepare("{0:#x} {0:#X} {0:#b} {0:#o} {0:#06x}").unwrap();
+    assert_eq!(prepared.format(&Pair(42, "")), format!("{0:#x} {0:#X} {0:#b} {0:#o} {0:#06x}", 42));
+}
+
+#[test]
+fn deferred() {
+    use std::cell::Cell;
+    use std::fmt;
+
+    struct Counted<'a>(&'a Cell<usize>);
+    impl<'a> fmt::Display for Counted<'a> {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            self.0.set(self.0.get() + 1);
+            f.write_str("counted")
+        }
+    }
+
+    #[derive(FormatArgs)]
+    struct Event<'a> {
+        level: u8,
+        detail: Counted<'a>,
+    }
+
+    fn log<D: fmt::Display>(enabled: bool, out: &mut String, message: D) {
+        if enabled {
+            out.push_str(&message.to_string());
+        }
+    }
+
+    let calls = Cell::new(0);
+    let event = Event { level: 2, detail: Counted(&calls) };
+    let prepared = PreparedFormat::<Event>::prepare("[{level}] {detail}").unwrap();
+    let mut out = String::new();
+    log(false, &mut out, prepared.deferred(&event));
+    assert_eq!(calls.get(), 0);
+    log(true, &mut out, prepared.deferred(&event));
+    assert_eq!(calls.get(), 1);
+    assert_eq!(out, "[2] counted");
+}
+
+#[test]
+fn format_utf16() {
+    let prepared = PreparedFormat::<Pair>::prepare("{1}: {0}").unwrap();
+    let utf16 = prepared.format_utf16(&Pair(-3, "Grüße"));
+    assert_eq!(utf16, "Grüße: -3".encode_utf16().collect::<Vec<_>>());
+}
+
+#[test]
+fn write_chunked() {
+    let prepared = PreparedFormat::<Pair>::prepare("{1:>8}: {0}").unwrap();
+    let mut out = Vec::new();
+    prepared.write_chunked(&Pair(-3, "Grüße"), &mut out, 3).unwrap();
+    assert_eq!(out, "   Grüße: -3".as_bytes());
+}
+
+#[test]
+fn conditional_sections() {
+    // decided each time a value is formatted
+    let prepared = PreparedFormat::<Pair>::prepare("{0}{1?: }").unwrap();
+    assert_eq!(prepared.format(&Pair(1, "one")), "1: one");
+    assert_eq!(prepared.format(&Pair(2, " ")), "2");
+    assert_eq!(prepared.format(&Pair(3, "")), "3");
+
+    let shape = prepared.shape();
+    let bound = PreparedFormat::<Pair>::from_shape(&shape).unwrap();
+    assert_eq!(bound.format(&Pair(4, "four")), "4: four");
+    assert_eq!(bound.format(&Pair(5, "")), "5");
+}
+
+#[test]
+fn fully_borrowed() {
+    assert!(PreparedFormat::<Pair>::prepare("{0} and {1}").unwrap().is_fully_borrowed());
+    assert!(!PreparedFormat::<Pair>::prepare("{0}{{}}{1}").unwrap().is_fully_borrowed());
+    assert!(!PreparedFormat::<Pair>::prepare("{0}").unwrap().into_owned().is_fully_borrowed());
+}
+
+#[test]
+fn next_param_precision() {
+    let prepared = PreparedFormat::<(usize, f64, usize, &str)>::prepare("{:.*}|{:>6.*}|").unwrap();
+    assert_eq!(prepared.format(&(2, 3.14159, 3, "abcdef")),
+               format!("{:.*}|{:>6.*}|", 2, 3.14159, 3, "abcdef"));
+    match PreparedFormat::<(i32, f64)>::prepare("{:.*}") {
+        Err(BadCount { idx: 0, .. }) => {}
+        other => panic!("{:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+fn write_fmt_atomic() {
+    let prepared = PreparedFormat::<Pair>::prepare("{1}={0}").unwrap();
+    let mut out = String::new();
+    prepared.write_fmt_atomic(&Pair(5, "five"), &mut out).unwrap();
+    assert_eq!(out, "five=5");
+}
+
+#[test]
+fn map_format() {
+    use std::collections::{BTreeMap, HashMap};
+    use runtime_fmt::map::MapFormat;
+
+    let prepared = MapFormat::<String>::prepare("{user}@{host} ({user:?})").unwrap();
+    assert_eq!(prepared.referenced_names().collect::<Vec<_>>(), ["user", "host"]);
+    let mut config = HashMap::new();
+    config.insert("user".to_owned(), "alice".to_owned());
+    config.insert("host".to_owned(), "example.org".to_owned());
+    config.insert("port".to_owned(), "22".to_owned());
+    assert_eq!(prepared.format(&config).unwrap(), "alice@example.org (\"alice\")");
+    config.remove("host");
+    match prepared.format(&config) {
+        Err(BadName(ref n)) => assert_eq!(n, "host"),
+        other => panic!("{:?}", other),
+    }
+
+    let prepared = MapFormat::<usize>::prepare("[{n:>w$}] {n:plural(1#item|items)}").unwrap();
+    let mut counts = BTreeMap::new();
+    counts.insert("n", 1);
+    counts.insert("w", 3);
+    assert_eq!(prepared.format(&counts).unwrap(), "[  1] item");
+    let mut out = String::new();
+    counts.insert("n", 12);
+    prepared.write_fmt(&counts, &mut out).unwrap();
+    assert_eq!(out, "[ 12] items");
+
+    // positions are looked up in the order of a map's own entries, if any
+    let prepared = MapFormat::<String>::prepare("{user}@{0}").unwrap();
+    match prepared.format(&config) {
+        Err(BadIndex(0)) => {}
+        other => panic!("{:?}", other),
+    }
+    let mut sorted = BTreeMap::new();
+    sorted.insert("user", "bob".to_owned());
+    sorted.insert("host", "example.org".to_owned());
+    assert_eq!(prepared.format(&sorted).unwrap(), "bob@example.org");
+    let prepared = MapFormat::<String>::prepare("{} {} {user} {1:>5}").unwrap();
+    assert_eq!(prepared.format(&sorted).unwrap(), "example.org bob bob   bob");
+    sorted.insert("admin", "root".to_owned());
+    assert_eq!(prepared.format(&sorted).unwrap(), "root example.org bob example.org");
+    sorted.clear();
+    sorted.insert("user", "bob".to_owned());
+    match prepared.format(&sorted) {
+        Err(BadIndex(1)) => {}
+        other => panic!("{:?}", other),
+    }
+    match MapFormat::<String>::prepare("{a} {b:x}") {
+        Err(UnsatisfiedFormat { idx: 1, must_implement: "LowerHex" }) => {}
+        other => panic!("{:?}", other.map(|_| ())),
+    }
+    match MapFormat::<String>::prepare("{a:w$}") {
+        Err(BadCount { idx: 1, .. }) => {}
+        other => panic!("{:?}", other.map(|_| ())),
+    }
+}